@@ -0,0 +1,104 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Datelike, Local, Timelike};
+use zip::{write::SimpleFileOptions, CompressionMethod, DateTime as ZipDateTime, ZipWriter};
+
+/// Packs files into a new ZIP archive.
+///
+/// Entries are named after the file, inside of `folder` if given.
+/// Level 0 stores the files uncompressed, 1 to 9 uses Deflate.
+pub fn write_zip(
+    path: &Path,
+    folder: Option<&str>,
+    files: &[PathBuf],
+    level: i64,
+) -> Result<(), Box<dyn Error>> {
+    let mut options = match level {
+        0 => SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+        _ => SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(level)),
+    };
+    if let Ok(modified) = modified_time() {
+        options = options.last_modified_time(modified);
+    }
+
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    for file in files {
+        let file_name = file.file_name().unwrap().to_string_lossy();
+        let name = match folder {
+            Some(folder) => format!("{}/{}", folder, file_name),
+            None => file_name.to_string(),
+        };
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(file)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Entries get the time of the run, ZIP stores local time without zone.
+fn modified_time() -> Result<ZipDateTime, Box<dyn Error>> {
+    let now = Local::now();
+    Ok(ZipDateTime::from_date_and_time(
+        now.year() as u16,
+        now.month() as u8,
+        now.day() as u8,
+        now.hour() as u8,
+        now.minute() as u8,
+        now.second() as u8,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, io::Read};
+    use zip::ZipArchive;
+
+    /// Names and contents of all entries.
+    fn entries(path: &Path) -> Vec<(String, String)> {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|idx| {
+                let mut entry = archive.by_index(idx).unwrap();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                (entry.name().to_string(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packs_files_into_folder() {
+        let folder = std::env::temp_dir().join(format!("csv-helper-zip-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let files = vec![folder.join("Nord.csv"), folder.join("Süd.csv")];
+        fs::write(&files[0], "Name\nAnna\n".repeat(100)).unwrap();
+        fs::write(&files[1], "Name\nBen\n").unwrap();
+
+        let deflated = folder.join("deflated.zip");
+        write_zip(&deflated, Some("Region"), &files, 9).unwrap();
+        assert_eq!(
+            entries(&deflated),
+            vec![
+                ("Region/Nord.csv".to_string(), "Name\nAnna\n".repeat(100)),
+                ("Region/Süd.csv".to_string(), "Name\nBen\n".to_string()),
+            ]
+        );
+
+        let stored = folder.join("stored.zip");
+        write_zip(&stored, None, &files[1..], 0).unwrap();
+        assert_eq!(
+            entries(&stored),
+            vec![("Süd.csv".to_string(), "Name\nBen\n".to_string())]
+        );
+        assert!(fs::metadata(&deflated).unwrap().len() < fs::metadata(&files[0]).unwrap().len());
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
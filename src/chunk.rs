@@ -0,0 +1,122 @@
+use std::ops::Range;
+
+use csv::StringRecord;
+
+/// Ranges of at most `size` rows over records with header, the header is not included.
+pub fn chunk_rows(records: &[StringRecord], size: usize) -> Vec<Range<usize>> {
+    let size = size.max(1);
+    (1..records.len())
+        .step_by(size)
+        .map(|start| start..(start + size).min(records.len()))
+        .collect()
+}
+
+/// Ranges of rows whose size plus `overhead` (header and BOM repeated in every part) stays
+/// within `limit` bytes, the header is not included.
+///
+/// A row which exceeds the limit on its own gets a part of its own.
+pub fn chunk_bytes(
+    records: &[StringRecord],
+    limit: usize,
+    overhead: usize,
+    mut size: impl FnMut(&StringRecord) -> usize,
+) -> Vec<Range<usize>> {
+    let mut chunks = vec![];
+    let (mut start, mut bytes) = (1, overhead);
+    for (row, record) in records.iter().enumerate().skip(1) {
+        let record_bytes = size(record);
+        if row > start && bytes + record_bytes > limit {
+            chunks.push(start..row);
+            start = row;
+            bytes = overhead;
+        }
+        bytes += record_bytes;
+    }
+    if start < records.len() {
+        chunks.push(start..records.len());
+    }
+    chunks
+}
+
+/// File stem of a part, numbered from 1 with as many digits as the number of parts.
+pub fn part_stem(stem: &str, part: usize, parts: usize) -> String {
+    format!(
+        "{}_part{:0width$}",
+        stem,
+        part,
+        width = parts.to_string().len()
+    )
+}
+
+/// Reads `50000`, `500 KB` or `10 MB`, units are decimal so `10 MB` is 10,000,000 bytes.
+pub fn parse_size(text: &str) -> Option<usize> {
+    let text = text.trim().to_uppercase();
+    let (number, factor) = match text.strip_suffix('B') {
+        Some(rest) if rest.ends_with('K') => (&rest[..rest.len() - 1], 1_000),
+        Some(rest) if rest.ends_with('M') => (&rest[..rest.len() - 1], 1_000_000),
+        Some(rest) if rest.ends_with('G') => (&rest[..rest.len() - 1], 1_000_000_000),
+        Some(rest) => (rest, 1),
+        None => (text.as_str(), 1),
+    };
+    number
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|size| *size > 0)
+        .and_then(|size| size.checked_mul(factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header and `rows` records whose single field is as long as given.
+    fn records(lengths: &[usize]) -> Vec<StringRecord> {
+        std::iter::once(StringRecord::from(vec!["header"]))
+            .chain(
+                lengths
+                    .iter()
+                    .map(|length| StringRecord::from(vec!["x".repeat(*length)])),
+            )
+            .collect()
+    }
+
+    #[test]
+    fn rows_per_part() {
+        assert_eq!(chunk_rows(&records(&[1; 5]), 2), vec![1..3, 3..5, 5..6]);
+        assert_eq!(chunk_rows(&records(&[1; 2]), 10), vec![1..3]);
+        assert_eq!(chunk_rows(&records(&[]), 2), vec![]);
+        assert_eq!(chunk_rows(&records(&[1; 2]), 0), vec![1..2, 2..3]);
+    }
+
+    #[test]
+    fn bytes_per_part() {
+        let size = |record: &StringRecord| record[0].len();
+        let records = records(&[4, 4, 4, 12, 1]);
+        assert_eq!(
+            chunk_bytes(&records, 10, 2, size),
+            vec![1..3, 3..4, 4..5, 5..6]
+        );
+        assert_eq!(chunk_bytes(&records, 100, 2, size), vec![1..6]);
+    }
+
+    #[test]
+    fn part_stems_sort_by_name() {
+        assert_eq!(part_stem("Nord", 1, 9), "Nord_part1");
+        assert_eq!(part_stem("Nord", 2, 10), "Nord_part02");
+        assert_eq!(part_stem("Nord", 7, 120), "Nord_part007");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("50000"), Some(50_000));
+        assert_eq!(parse_size(" 500 kb "), Some(500_000));
+        assert_eq!(parse_size("10 MB"), Some(10_000_000));
+        assert_eq!(parse_size("2GB"), Some(2_000_000_000));
+        assert_eq!(parse_size("12 B"), Some(12));
+        assert_eq!(parse_size("0"), None);
+        assert_eq!(parse_size("1.5 MB"), None);
+        assert_eq!(parse_size("MB"), None);
+        assert_eq!(parse_size("-5"), None);
+    }
+}
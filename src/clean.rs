@@ -0,0 +1,263 @@
+use std::{iter::Peekable, str::Chars};
+
+use csv::StringRecord;
+
+/// Values replaced by an empty field by `null` without arguments.
+pub const NULL_TOKENS: [&str; 6] = ["N/A", "NA", "n.a.", "NULL", "-", "--"];
+
+/// Short reference of the rule syntax for the wizard.
+pub const CLEANING_HELP: &str = "Column = step, step, ...  (* for all columns)
+Steps: trim, collapse, upper, lower, title,
+       replace(\"find\", \"with\"), null or null(\"N/A\", \"-\")";
+
+/// Single cleaning step of a column.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// Removes leading and trailing whitespace, including non-breaking spaces.
+    Trim,
+    /// Turns every run of whitespace into a single space.
+    Collapse,
+    Upper,
+    Lower,
+    /// First letter of every word upper case, the rest lower case.
+    Title,
+    Replace(String, String),
+    /// Values equal to one of the tokens, ignoring case and surrounding space, become empty.
+    Null(Vec<String>),
+}
+
+impl Step {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Step::Trim => value.trim().to_string(),
+            Step::Collapse => value.split_whitespace().collect::<Vec<_>>().join(" "),
+            Step::Upper => value.to_uppercase(),
+            Step::Lower => value.to_lowercase(),
+            Step::Title => title_case(value),
+            Step::Replace(find, with) if !find.is_empty() => value.replace(find.as_str(), with),
+            Step::Replace(..) => value.to_string(),
+            Step::Null(tokens) => match tokens
+                .iter()
+                .any(|token| token.trim().to_lowercase() == value.trim().to_lowercase())
+            {
+                true => String::new(),
+                false => value.to_string(),
+            },
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Step::Trim => "trim".to_string(),
+            Step::Collapse => "collapse".to_string(),
+            Step::Upper => "upper".to_string(),
+            Step::Lower => "lower".to_string(),
+            Step::Title => "title".to_string(),
+            Step::Replace(find, with) => format!("replace(\"{}\", \"{}\")", find, with),
+            Step::Null(_) => "null".to_string(),
+        }
+    }
+}
+
+fn title_case(value: &str) -> String {
+    let mut title = String::with_capacity(value.len());
+    let mut word_start = true;
+    for c in value.chars() {
+        match word_start {
+            true => title.extend(c.to_uppercase()),
+            false => title.extend(c.to_lowercase()),
+        }
+        word_start = !c.is_alphanumeric();
+    }
+    title
+}
+
+/// Parses `Column = step, step(...)` lines, `*` applies the steps to all columns.
+pub fn parse_rules(lines: &[String]) -> Result<Vec<(String, Vec<Step>)>, String> {
+    let mut rules = vec![];
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let (column, steps) = line
+            .split_once('=')
+            .ok_or_else(|| format!("\"{}\" is not \"Column = steps\".", line))?;
+        let steps = parse_steps(steps).map_err(|error| format!("{}: {}", column.trim(), error))?;
+        rules.push((column.trim().to_string(), steps));
+    }
+    Ok(rules)
+}
+
+/// Steps of every header in rule order, unknown columns are an error.
+pub fn column_steps(lines: &[String], headers: &StringRecord) -> Result<Vec<Vec<Step>>, String> {
+    let rules = parse_rules(lines)?;
+    if let Some((column, _)) = rules
+        .iter()
+        .find(|(column, _)| column != "*" && !headers.iter().any(|header| header == column))
+    {
+        return Err(format!("Unknown column {}.", column));
+    }
+    Ok(headers
+        .iter()
+        .map(|header| {
+            rules
+                .iter()
+                .filter(|(column, _)| column == "*" || column == header)
+                .flat_map(|(_, steps)| steps.iter().cloned())
+                .collect()
+        })
+        .collect())
+}
+
+/// Applies the steps of each column to its field.
+pub fn clean_record(record: &StringRecord, steps: &[Vec<Step>]) -> StringRecord {
+    record
+        .iter()
+        .zip(steps)
+        .map(|(field, steps)| clean(field, steps))
+        .collect()
+}
+
+pub fn clean(field: &str, steps: &[Step]) -> String {
+    steps
+        .iter()
+        .fold(field.to_string(), |value, step| step.apply(&value))
+}
+
+/// Splits `step, step("a", "b")` at commas outside of parentheses and quotes.
+fn parse_steps(source: &str) -> Result<Vec<Step>, String> {
+    let mut steps = vec![];
+    let mut chars = source.chars().peekable();
+    loop {
+        skip_whitespace(&mut chars);
+        let name: String = std::iter::from_fn(|| chars.next_if(|c| c.is_alphanumeric())).collect();
+        skip_whitespace(&mut chars);
+        let mut arguments = vec![];
+        if chars.next_if_eq(&'(').is_some() {
+            loop {
+                skip_whitespace(&mut chars);
+                if chars.next_if_eq(&')').is_some() {
+                    break;
+                }
+                if chars.next() != Some('"') {
+                    return Err("Arguments must be quoted.".to_string());
+                }
+                let mut argument = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            argument.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => argument.push(c),
+                        None => return Err("Missing '\"'.".to_string()),
+                    }
+                }
+                arguments.push(argument);
+                skip_whitespace(&mut chars);
+                chars.next_if_eq(&',');
+            }
+        }
+        steps.push(step(&name, arguments)?);
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(',') => continue,
+            None => return Ok(steps),
+            Some(c) => return Err(format!("Unexpected '{}'.", c)),
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn step(name: &str, mut arguments: Vec<String>) -> Result<Step, String> {
+    let step = match (name.to_lowercase().as_str(), arguments.len()) {
+        ("trim", 0) => Step::Trim,
+        ("collapse", 0) => Step::Collapse,
+        ("upper", 0) => Step::Upper,
+        ("lower", 0) => Step::Lower,
+        ("title", 0) => Step::Title,
+        ("replace", 2) => {
+            let with = arguments.pop().unwrap();
+            Step::Replace(arguments.pop().unwrap(), with)
+        }
+        ("null", 0) => Step::Null(NULL_TOKENS.iter().map(|token| token.to_string()).collect()),
+        ("null", _) => Step::Null(arguments),
+        ("", _) => return Err("Missing step.".to_string()),
+        _ => return Err(format!("Unknown step or arguments of {}.", name)),
+    };
+    Ok(step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_steps_with_arguments() {
+        let rules = parse_rules(&lines(&[
+            "Name = trim, collapse, title",
+            "City=replace(\"a, b\", \"say \"\"c\"\"\"), null(\"?\")",
+        ]))
+        .unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                (
+                    "Name".to_string(),
+                    vec![Step::Trim, Step::Collapse, Step::Title]
+                ),
+                (
+                    "City".to_string(),
+                    vec![
+                        Step::Replace("a, b".to_string(), "say \"c\"".to_string()),
+                        Step::Null(vec!["?".to_string()]),
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let error = |line: &str| parse_rules(&lines(&[line])).unwrap_err();
+        assert_eq!(
+            error("Name trim"),
+            "\"Name trim\" is not \"Column = steps\"."
+        );
+        assert_eq!(
+            error("Name = shout"),
+            "Name: Unknown step or arguments of shout."
+        );
+        assert_eq!(
+            error("Name = replace(a, b)"),
+            "Name: Arguments must be quoted."
+        );
+        assert_eq!(error("Name = trim,"), "Name: Missing step.");
+        assert_eq!(error("Name = null(\"x)"), "Name: Missing '\"'.");
+        assert_eq!(error("Name = trim;"), "Name: Unexpected ';'.");
+    }
+
+    #[test]
+    fn cleans_columns_in_rule_order() {
+        let headers = StringRecord::from(vec!["Name", "City"]);
+        let steps = column_steps(&lines(&["* = trim", "City = upper, null"]), &headers).unwrap();
+        let record = StringRecord::from(vec!["  anna  maria ", " n/a "]);
+        assert_eq!(
+            clean_record(&record, &steps),
+            StringRecord::from(vec!["anna  maria", ""])
+        );
+        assert_eq!(
+            clean("  anna  maria o'neil", &[Step::Collapse, Step::Title]),
+            "Anna Maria O'Neil"
+        );
+        assert_eq!(
+            column_steps(&lines(&["Town = trim"]), &headers).unwrap_err(),
+            "Unknown column Town."
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use arrow_array::{
+    ArrayRef, BooleanArray, Date32Array, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema as ArrowSchema, SchemaRef, TimeUnit};
+use chrono::NaiveDate;
+use csv::StringRecord;
+
+use crate::{
+    infer::{ColumnType, Schema, Value},
+    utils::{unique_headers, ParsedTime},
+};
+
+/// Arrow schema of the output, shared by all categories.
+///
+/// Without type inference every column is a string column.
+pub fn arrow_schema(headers: &StringRecord, schema: &Schema) -> SchemaRef {
+    let fields: Vec<Field> = unique_headers(headers)
+        .into_iter()
+        .enumerate()
+        .map(|(col, name)| Field::new(name, data_type(schema.column_type(col)), true))
+        .collect();
+    Arc::new(ArrowSchema::new(fields))
+}
+
+fn data_type(column_type: ColumnType) -> DataType {
+    match column_type {
+        ColumnType::Text => DataType::Utf8,
+        ColumnType::Integer => DataType::Int64,
+        ColumnType::Decimal { .. } | ColumnType::Percentage { .. } => DataType::Float64,
+        ColumnType::Boolean => DataType::Boolean,
+        ColumnType::Date => DataType::Date32,
+        ColumnType::DateTime => DataType::Timestamp(TimeUnit::Millisecond, None),
+    }
+}
+
+/// Converts the records of a category (header excluded) into one batch.
+///
+/// Empty or unparsable fields of typed columns become null.
+pub fn record_batch(
+    arrow_schema: SchemaRef,
+    records: &[StringRecord],
+    schema: &Schema,
+) -> Result<RecordBatch, ArrowError> {
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let columns: Vec<ArrayRef> = (0..arrow_schema.fields().len())
+        .map(|col| {
+            let values = records
+                .iter()
+                .map(move |record| schema.value(col, record.get(col).unwrap_or_default()));
+            let array: ArrayRef = match schema.column_type(col) {
+                ColumnType::Text => Arc::new(StringArray::from(
+                    records
+                        .iter()
+                        .map(|record| record.get(col).unwrap_or_default())
+                        .collect::<Vec<_>>(),
+                )),
+                ColumnType::Integer => Arc::new(Int64Array::from(
+                    values
+                        .map(|value| match value {
+                            Value::Number(number) => Some(number as i64),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                ColumnType::Decimal { .. } | ColumnType::Percentage { .. } => {
+                    Arc::new(Float64Array::from(
+                        values
+                            .map(|value| match value {
+                                Value::Number(number) => Some(number),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>(),
+                    ))
+                }
+                ColumnType::Boolean => Arc::new(BooleanArray::from(
+                    values
+                        .map(|value| match value {
+                            Value::Boolean(value) => Some(value),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                ColumnType::Date => Arc::new(Date32Array::from(
+                    values
+                        .map(|value| match value {
+                            Value::Date(ParsedTime::Date(date)) => {
+                                Some((date - epoch).num_days() as i32)
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                ColumnType::DateTime => Arc::new(TimestampMillisecondArray::from(
+                    values
+                        .map(|value| match value {
+                            Value::Date(ParsedTime::Date(date)) => Some(
+                                date.and_hms_opt(0, 0, 0)
+                                    .unwrap()
+                                    .and_utc()
+                                    .timestamp_millis(),
+                            ),
+                            Value::Date(ParsedTime::DateTime(datetime)) => {
+                                Some(datetime.and_utc().timestamp_millis())
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+            };
+            array
+        })
+        .collect();
+    RecordBatch::try_new(arrow_schema, columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::Separator;
+    use arrow_array::Array;
+
+    #[test]
+    fn typed_columns_with_nulls() {
+        let headers = StringRecord::from(vec!["Name", "Amount", "Count", "Paid", "Day", "Time"]);
+        let schema = Schema {
+            column_types: vec![
+                ColumnType::Text,
+                ColumnType::Decimal {
+                    separator: Separator::Comma,
+                    scale: 2,
+                },
+                ColumnType::Integer,
+                ColumnType::Boolean,
+                ColumnType::Date,
+                ColumnType::DateTime,
+            ],
+            date_patterns: vec![vec!["%Y-%m-%d".to_string(), "%Y-%m-%d %H:%M:%S".to_string()]; 6],
+        };
+        let records = vec![
+            StringRecord::from(vec![
+                "Anna",
+                "1.234,5",
+                "3",
+                "wahr",
+                "1970-01-02",
+                "1970-01-01 00:00:01",
+            ]),
+            StringRecord::from(vec!["", "", "x", "", "", "1970-01-02"]),
+        ];
+
+        let arrow_schema = arrow_schema(&headers, &schema);
+        let data_types: Vec<DataType> = arrow_schema
+            .fields()
+            .iter()
+            .map(|field| field.data_type().clone())
+            .collect();
+        assert_eq!(
+            data_types,
+            vec![
+                DataType::Utf8,
+                DataType::Float64,
+                DataType::Int64,
+                DataType::Boolean,
+                DataType::Date32,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+            ]
+        );
+
+        let batch = record_batch(arrow_schema, &records, &schema).unwrap();
+        let column = |col: usize| batch.column(col).clone();
+        let texts = column(0);
+        let texts = texts.as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(
+            (texts.value(0), texts.value(1), texts.is_null(1)),
+            ("Anna", "", false)
+        );
+        let amounts = column(1);
+        let amounts = amounts.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!((amounts.value(0), amounts.is_null(1)), (1234.5, true));
+        let counts = column(2);
+        let counts = counts.as_any().downcast_ref::<Int64Array>().unwrap();
+        assert_eq!((counts.value(0), counts.is_null(1)), (3, true));
+        let paid = column(3);
+        let paid = paid.as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert_eq!((paid.value(0), paid.is_null(1)), (true, true));
+        let days = column(4);
+        let days = days.as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!((days.value(0), days.is_null(1)), (1, true));
+        let times = column(5);
+        let times = times
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        assert_eq!((times.value(0), times.value(1)), (1000, 86_400_000));
+    }
+}
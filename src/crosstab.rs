@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use csv::StringRecord;
+
+use crate::infer::{ColumnType, Value};
+
+/// Crosstab of all categories, written to the output folder next to the category files.
+pub const CROSSTAB_FILE: &str = "crosstab.xlsx";
+
+/// Label of empty values of the crosstab column.
+pub const EMPTY_VALUE: &str = "(empty)";
+
+/// Counts or sums of the rows of each category per value of a second column.
+pub struct Crosstab {
+    /// Distinct values of the crosstab column, sorted.
+    pub values: Vec<String>,
+    /// Category with one cell per value.
+    pub rows: Vec<(String, Vec<f64>)>,
+}
+
+impl Crosstab {
+    /// Cross-tabulates records without header, `sum_column` is summed instead of counting rows.
+    ///
+    /// Fields of the sum column are read with its column type, the ones which don't fit are
+    /// left out.
+    pub fn new<'a>(
+        categories: impl Iterator<Item = (&'a str, &'a [StringRecord])>,
+        column: usize,
+        sum_column: Option<(usize, ColumnType)>,
+    ) -> Crosstab {
+        let mut cells: Vec<(String, HashMap<&str, f64>)> = vec![];
+        for (category, records) in categories {
+            let mut row = HashMap::new();
+            for record in records {
+                let value = record.get(column).unwrap_or_default();
+                let amount = match sum_column {
+                    Some((col, column_type)) => {
+                        match column_type.parse(record.get(col).unwrap_or_default()) {
+                            Value::Number(number) => number,
+                            _ => 0.0,
+                        }
+                    }
+                    None => 1.0,
+                };
+                *row.entry(value).or_insert(0.0) += amount;
+            }
+            cells.push((category.to_string(), row));
+        }
+
+        let mut values: Vec<&str> = cells
+            .iter()
+            .flat_map(|(_, row)| row.keys().copied())
+            .collect();
+        values.sort_unstable();
+        values.dedup();
+        let rows = cells
+            .iter()
+            .map(|(category, row)| {
+                let cells = values
+                    .iter()
+                    .map(|value| row.get(value).copied().unwrap_or(0.0))
+                    .collect();
+                (category.clone(), cells)
+            })
+            .collect();
+        Crosstab {
+            values: values
+                .iter()
+                .map(|value| match value.trim().is_empty() {
+                    true => EMPTY_VALUE.to_string(),
+                    false => value.to_string(),
+                })
+                .collect(),
+            rows,
+        }
+    }
+
+    /// Totals of every value column.
+    pub fn column_totals(&self) -> Vec<f64> {
+        (0..self.values.len())
+            .map(|idx| self.rows.iter().map(|(_, cells)| cells[idx]).sum())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::Separator;
+
+    fn records(rows: &[(&str, &str)]) -> Vec<StringRecord> {
+        rows.iter()
+            .map(|(value, amount)| StringRecord::from(vec![*value, *amount]))
+            .collect()
+    }
+
+    #[test]
+    fn counts_and_sums_with_column_type() {
+        let (north, south) = (
+            records(&[("x", "1.5"), ("", "1.234"), ("x", "n/a")]),
+            records(&[("y", "2.25")]),
+        );
+        let categories = || vec![("North", &north[..]), ("South", &south[..])].into_iter();
+
+        let counts = Crosstab::new(categories(), 0, None);
+        assert_eq!(counts.values, vec![EMPTY_VALUE, "x", "y"]);
+        assert_eq!(counts.rows[0], ("North".to_string(), vec![1.0, 2.0, 0.0]));
+        assert_eq!(counts.column_totals(), vec![1.0, 2.0, 1.0]);
+
+        let english = ColumnType::Decimal {
+            separator: Separator::Point,
+            scale: 3,
+        };
+        let sums = Crosstab::new(categories(), 0, Some((1, english)));
+        assert_eq!(sums.rows[0].1, vec![1.234, 1.5, 0.0]);
+        assert_eq!(sums.rows[1].1, vec![0.0, 0.0, 2.25]);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use csv::StringRecord;
+use sha2::{Digest, Sha256};
+
+/// Removed rows, written to the output folder if requested.
+pub const DUPLICATES_FILE: &str = "_duplicates.csv";
+
+/// Finds duplicated rows while the input is read.
+///
+/// Rows are compared by their key columns, or completely without key columns. Within each
+/// category the category is part of the key, compared ignoring case like the categories.
+///
+/// Only a SHA-256 hash of each key is kept with the position of its row, rows are cloned
+/// once they turn out to be duplicates.
+pub struct Deduplicator {
+    key_columns: Vec<usize>,
+    per_category: bool,
+    keep_last: bool,
+    /// Input header followed by the removed rows, `None` if they aren't kept.
+    duplicates: Option<Vec<StringRecord>>,
+    /// Kept row of each key hash: category and position in the category.
+    kept: HashMap<[u8; 32], (String, usize)>,
+    /// Rows replaced by a later duplicate, only when keeping the last one, with their index
+    /// in `duplicates` until [`Deduplicator::add_replaced`] fills it in.
+    replaced: HashMap<(String, usize), usize>,
+    pub removed: usize,
+}
+
+impl Deduplicator {
+    /// Without `header` removed rows are only counted.
+    pub fn new(
+        key_columns: Vec<usize>,
+        per_category: bool,
+        keep_last: bool,
+        header: Option<&StringRecord>,
+    ) -> Deduplicator {
+        Deduplicator {
+            key_columns,
+            per_category,
+            keep_last,
+            duplicates: header.map(|header| vec![header.clone()]),
+            kept: HashMap::new(),
+            replaced: HashMap::new(),
+            removed: 0,
+        }
+    }
+
+    /// Whether the record is kept for now, `position` is its index in the records of `category`.
+    ///
+    /// When keeping the last row an earlier duplicate is replaced instead, see
+    /// [`Deduplicator::is_replaced`].
+    pub fn insert(&mut self, record: &StringRecord, category: &str, position: usize) -> bool {
+        let key = self.key(record, category);
+        match self.kept.get_mut(&key) {
+            None => {
+                self.kept.insert(key, (category.to_string(), position));
+                true
+            }
+            Some(kept) if self.keep_last => {
+                let replaced = std::mem::replace(kept, (category.to_string(), position));
+                self.removed += 1;
+                // the caller still holds the replaced row and adds it after reading
+                let idx = match self.duplicates.as_mut() {
+                    Some(duplicates) => {
+                        duplicates.push(StringRecord::new());
+                        duplicates.len() - 1
+                    }
+                    None => 0,
+                };
+                self.replaced.insert(replaced, idx);
+                true
+            }
+            Some(_) => {
+                self.removed += 1;
+                if let Some(duplicates) = self.duplicates.as_mut() {
+                    duplicates.push(record.clone());
+                }
+                false
+            }
+        }
+    }
+
+    /// Hash of the key fields, each prefixed by its length so fields can't run together.
+    fn key(&self, record: &StringRecord, category: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        let mut add = |field: &str| {
+            hasher.update((field.len() as u64).to_le_bytes());
+            hasher.update(field);
+        };
+        match self.key_columns.is_empty() {
+            true => record.iter().for_each(&mut add),
+            false => self
+                .key_columns
+                .iter()
+                .for_each(|&col| add(record.get(col).unwrap_or_default())),
+        }
+        if self.per_category {
+            add(category);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Whether a kept row was replaced by a later duplicate.
+    pub fn is_replaced(&self, category: &str, position: usize) -> bool {
+        self.replaced
+            .contains_key(&(category.to_string(), position))
+    }
+
+    /// Adds a replaced row to the removed rows, in place of the later row which replaced it.
+    pub fn add_replaced(&mut self, category: &str, position: usize, record: StringRecord) {
+        let idx = self.replaced[&(category.to_string(), position)];
+        if let Some(duplicates) = self.duplicates.as_mut() {
+            duplicates[idx] = record;
+        }
+    }
+
+    /// Input header and removed rows, if they were kept.
+    pub fn duplicates(&self) -> Option<&[StringRecord]> {
+        self.duplicates.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// `(category, name, city)`, Anna is in both categories and twice in Nord.
+    const ROWS: [(&str, &str, &str); 5] = [
+        ("nord", "Anna", "Köln"),
+        ("süd", "Anna", "Bonn"),
+        ("nord", "Anna", "Kiel"),
+        ("nord", "Ben", "Bonn"),
+        ("süd", "Ben", "Bonn"),
+    ];
+
+    fn joined(records: &[StringRecord]) -> Vec<String> {
+        records
+            .iter()
+            .map(|record| record.iter().collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    /// Runs the rows through the deduplicator like `read_csv` and returns the rows kept per
+    /// category and the removed rows.
+    fn deduplicate(mut dedup: Deduplicator) -> (BTreeMap<&'static str, Vec<String>>, Vec<String>) {
+        let header = StringRecord::from(vec!["Name", "City"]);
+        let mut categories: BTreeMap<&str, Vec<StringRecord>> = BTreeMap::new();
+        for (category, name, city) in ROWS.iter() {
+            let records = categories
+                .entry(*category)
+                .or_insert_with(|| vec![header.clone()]);
+            let record = StringRecord::from(vec![*name, *city]);
+            if dedup.insert(&record, category, records.len()) {
+                records.push(record);
+            }
+        }
+        let kept = categories
+            .into_iter()
+            .map(|(category, mut records)| {
+                let mut kept = vec![];
+                for (position, record) in (1..).zip(records.split_off(1)) {
+                    match dedup.is_replaced(category, position) {
+                        true => dedup.add_replaced(category, position, record),
+                        false => kept.push(record),
+                    }
+                }
+                (category, joined(&kept))
+            })
+            .collect();
+        let duplicates = dedup.duplicates().unwrap();
+        assert_eq!(duplicates[0], header);
+        assert_eq!(dedup.removed, duplicates.len() - 1);
+        (kept, joined(&duplicates[1..]))
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn keep_first_or_last_across_categories() {
+        let header = StringRecord::from(vec!["Name", "City"]);
+        let (kept, removed) = deduplicate(Deduplicator::new(vec![0], false, false, Some(&header)));
+        assert_eq!(kept["nord"], names(&["Anna Köln", "Ben Bonn"]));
+        assert_eq!(kept["süd"], names(&[]));
+        assert_eq!(removed, names(&["Anna Bonn", "Anna Kiel", "Ben Bonn"]));
+
+        let (kept, removed) = deduplicate(Deduplicator::new(vec![0], false, true, Some(&header)));
+        assert_eq!(kept["nord"], names(&["Anna Kiel"]));
+        assert_eq!(kept["süd"], names(&["Ben Bonn"]));
+        assert_eq!(removed, names(&["Anna Köln", "Anna Bonn", "Ben Bonn"]));
+    }
+
+    #[test]
+    fn keep_first_or_last_per_category() {
+        let header = StringRecord::from(vec!["Name", "City"]);
+        let (kept, removed) = deduplicate(Deduplicator::new(vec![0], true, false, Some(&header)));
+        assert_eq!(kept["nord"], names(&["Anna Köln", "Ben Bonn"]));
+        assert_eq!(kept["süd"], names(&["Anna Bonn", "Ben Bonn"]));
+        assert_eq!(removed, names(&["Anna Kiel"]));
+
+        let (kept, removed) = deduplicate(Deduplicator::new(vec![0], true, true, Some(&header)));
+        assert_eq!(kept["nord"], names(&["Anna Kiel", "Ben Bonn"]));
+        assert_eq!(kept["süd"], names(&["Anna Bonn", "Ben Bonn"]));
+        assert_eq!(removed, names(&["Anna Köln"]));
+    }
+
+    #[test]
+    fn complete_rows_without_key_columns() {
+        let header = StringRecord::from(vec!["Name", "City"]);
+        let (kept, removed) = deduplicate(Deduplicator::new(vec![], false, false, Some(&header)));
+        assert_eq!(kept["nord"], names(&["Anna Köln", "Anna Kiel", "Ben Bonn"]));
+        assert_eq!(kept["süd"], names(&["Anna Bonn"]));
+        assert_eq!(removed, names(&["Ben Bonn"]));
+
+        let mut counting = Deduplicator::new(vec![], false, false, None);
+        let record = StringRecord::from(vec!["Anna", "Köln"]);
+        assert!(counting.insert(&record, "nord", 1));
+        assert!(!counting.insert(&record, "nord", 2));
+        assert!(counting.insert(&StringRecord::from(vec!["AnnaK", "öln"]), "nord", 2));
+        assert_eq!((counting.removed, counting.duplicates()), (1, None));
+    }
+}
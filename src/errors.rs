@@ -26,3 +26,125 @@ impl fmt::Display for DirectoryError {
 }
 
 impl Error for DirectoryError {}
+
+#[derive(Debug, Clone)]
+pub struct ArchiveError;
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "archive already exist!\n
+            Choose different output folder or remove\n
+            ZIP file with same name as selected options."
+        )
+    }
+}
+
+impl Error for ArchiveError {}
+
+#[derive(Debug, Clone)]
+pub struct AppendError;
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "appending not possible!\n
+            Only CSV, JSON and NDJSON files can be appended,\n
+            without parts or ZIP archives replacing them."
+        )
+    }
+}
+
+impl Error for AppendError {}
+
+#[derive(Debug, Clone)]
+pub struct HeaderMismatchError;
+
+impl fmt::Display for HeaderMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "existing file has different headers!\n
+            Choose a different policy or output folder."
+        )
+    }
+}
+
+impl Error for HeaderMismatchError {}
+
+#[derive(Debug, Clone)]
+pub struct CancelError;
+
+impl fmt::Display for CancelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cancelled!\n
+            No files have been written."
+        )
+    }
+}
+
+impl Error for CancelError {}
+
+#[derive(Debug, Clone)]
+pub struct ExpressionError;
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid derived column!\n
+            Check the expressions of the derived columns."
+        )
+    }
+}
+
+impl Error for ExpressionError {}
+
+#[derive(Debug, Clone)]
+pub struct CleaningError;
+
+impl fmt::Display for CleaningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid cleaning rule!\n
+            Check the cleaning rules of the columns."
+        )
+    }
+}
+
+impl Error for CleaningError {}
+
+#[derive(Debug, Clone)]
+pub struct MaskingError;
+
+impl fmt::Display for MaskingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid mask!\n
+            Check the masks of the columns."
+        )
+    }
+}
+
+impl Error for MaskingError {}
+
+#[derive(Debug, Clone)]
+pub struct LookupError;
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid lookup!\n
+            Check the reference file and the key columns."
+        )
+    }
+}
+
+impl Error for LookupError {}
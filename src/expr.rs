@@ -0,0 +1,673 @@
+use chrono::{Datelike, NaiveDate};
+use csv::StringRecord;
+
+use crate::{
+    infer::parse_number,
+    naming::{is_valid_date_pattern, try_format},
+    utils::{try_parse_time, ParsedTime},
+};
+
+/// Functions with their minimum and maximum number of arguments.
+const FUNCTIONS: [(&str, Function, usize, usize); 21] = [
+    ("upper", Function::Upper, 1, 1),
+    ("lower", Function::Lower, 1, 1),
+    ("trim", Function::Trim, 1, 1),
+    ("len", Function::Len, 1, 1),
+    ("left", Function::Left, 2, 2),
+    ("right", Function::Right, 2, 2),
+    ("mid", Function::Mid, 3, 3),
+    ("replace", Function::Replace, 3, 3),
+    ("concat", Function::Concat, 1, usize::MAX),
+    ("coalesce", Function::Coalesce, 1, usize::MAX),
+    ("round", Function::Round, 1, 2),
+    ("abs", Function::Abs, 1, 1),
+    ("floor", Function::Floor, 1, 1),
+    ("ceil", Function::Ceil, 1, 1),
+    ("min", Function::Min, 1, usize::MAX),
+    ("max", Function::Max, 1, usize::MAX),
+    ("year", Function::Year, 1, 1),
+    ("month", Function::Month, 1, 1),
+    ("day", Function::Day, 1, 1),
+    ("date", Function::Date, 2, 2),
+    ("format", Function::Format, 2, 2),
+];
+
+/// Short reference of the expression syntax for the wizard.
+pub const EXPRESSION_HELP: &str =
+    "Columns: Name or [Name with spaces], text: \"text\", numbers: 1.5
+Operators: + - * / and & to join text
+Text: upper, lower, trim, len, left, right, mid, replace, concat, coalesce
+Numbers: round, abs, floor, ceil, min, max
+Dates: year, month, day, date(text, pattern), format(date, pattern)";
+
+#[derive(Clone, Copy, Debug)]
+enum Function {
+    Upper,
+    Lower,
+    Trim,
+    Len,
+    Left,
+    Right,
+    Mid,
+    Replace,
+    Concat,
+    Coalesce,
+    Round,
+    Abs,
+    Floor,
+    Ceil,
+    Min,
+    Max,
+    Year,
+    Month,
+    Day,
+    Date,
+    Format,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Join,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Text(String),
+    Number(f64),
+    Column(usize),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+/// Intermediate value while evaluating.
+enum Datum {
+    Text(String),
+    Number(f64),
+    Date(ParsedTime),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Name(String),
+    Column(String),
+    Symbol(char),
+}
+
+/// Parsed expression of a derived column.
+///
+/// Fields are text, numbers are recognized in German and English notation and dates with
+/// the date patterns of their column. If a value doesn't fit, the result is empty.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    /// Parses an expression, column names are resolved against `headers`.
+    pub fn parse(source: &str, headers: &StringRecord) -> Result<Expression, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            headers,
+        };
+        let root = parser.join()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Expression { root }),
+            Some(token) => Err(format!("Unexpected {}.", describe(token))),
+        }
+    }
+
+    /// Result for a record, `date_patterns` holds the patterns of every column and
+    /// `general_patterns` is used for dates computed from text.
+    pub fn evaluate(
+        &self,
+        record: &StringRecord,
+        date_patterns: &[Vec<String>],
+        general_patterns: &[String],
+    ) -> String {
+        let context = Context {
+            record,
+            date_patterns,
+            general_patterns,
+        };
+        match context.evaluate(&self.root) {
+            Some(Datum::Text(text)) => text,
+            Some(Datum::Number(number)) => format_number(number),
+            Some(Datum::Date(date)) => date.to_iso(),
+            None => String::new(),
+        }
+    }
+}
+
+/// Parses `(name, expression)` definitions in order, each may use the ones before.
+///
+/// Returns the input headers followed by the derived names and the expressions.
+pub fn parse_derived_columns(
+    headers: &StringRecord,
+    derived_columns: &[(String, String)],
+) -> Result<(StringRecord, Vec<Expression>), String> {
+    let mut headers = headers.clone();
+    let mut expressions = vec![];
+    for (name, source) in derived_columns {
+        if name.is_empty() || headers.iter().any(|header| header == name) {
+            return Err(format!("\"{}\" is empty or already a column.", name));
+        }
+        expressions.push(
+            Expression::parse(source, &headers).map_err(|error| format!("{}: {}", name, error))?,
+        );
+        headers.push_field(name);
+    }
+    Ok((headers, expressions))
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' | '.' => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                    number.push(c);
+                    chars.next();
+                }
+                match number.parse() {
+                    Ok(number) => tokens.push(Token::Number(number)),
+                    Err(_) => return Err(format!("Invalid number {}.", number)),
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            text.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err("Missing '\"'.".to_string()),
+                    }
+                }
+                tokens.push(Token::Text(text));
+            }
+            '[' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c) => name.push(c),
+                        None => return Err("Missing ']'.".to_string()),
+                    }
+                }
+                tokens.push(Token::Column(name));
+            }
+            '+' | '-' | '*' | '/' | '&' | '(' | ')' | ',' => {
+                chars.next();
+                tokens.push(Token::Symbol(c));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Name(name));
+            }
+            c => return Err(format!("Unexpected '{}'.", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(number) => format!("number {}", number),
+        Token::Text(text) => format!("text \"{}\"", text),
+        Token::Name(name) => format!("name {}", name),
+        Token::Column(name) => format!("column [{}]", name),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+    }
+}
+
+/// Recursive descent, `&` binds weakest, then `+ -`, then `* /`.
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    headers: &'a StringRecord,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, symbols: &[char]) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.position += 1;
+                Some(*symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        match self.accept(&[symbol]) {
+            Some(_) => Ok(()),
+            None => Err(format!("Expected '{}'.", symbol)),
+        }
+    }
+
+    fn join(&mut self) -> Result<Node, String> {
+        let mut node = self.sum()?;
+        while self.accept(&['&']).is_some() {
+            node = Node::Binary(Operator::Join, Box::new(node), Box::new(self.sum()?));
+        }
+        Ok(node)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(symbol) = self.accept(&['+', '-']) {
+            let operator = match symbol {
+                '+' => Operator::Add,
+                _ => Operator::Subtract,
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.product()?));
+        }
+        Ok(node)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(symbol) = self.accept(&['*', '/']) {
+            let operator = match symbol {
+                '*' => Operator::Multiply,
+                _ => Operator::Divide,
+            };
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.accept(&['-']) {
+            Some(_) => Ok(Node::Negate(Box::new(self.unary()?))),
+            None => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Text(text)) => Ok(Node::Text(text)),
+            Some(Token::Column(name)) => self.column(&name),
+            Some(Token::Name(name)) if self.accept(&['(']).is_some() => self.call(&name),
+            Some(Token::Name(name)) => self.column(&name),
+            Some(Token::Symbol('(')) => {
+                let node = self.join()?;
+                self.expect(')')?;
+                Ok(node)
+            }
+            Some(token) => Err(format!("Unexpected {}.", describe(&token))),
+            None => Err("Unexpected end.".to_string()),
+        }
+    }
+
+    fn column(&self, name: &str) -> Result<Node, String> {
+        match self.headers.iter().position(|header| header == name) {
+            Some(col) => Ok(Node::Column(col)),
+            None => Err(format!("Unknown column {}.", name)),
+        }
+    }
+
+    /// Arguments of a function, the opening parenthesis is already consumed.
+    fn call(&mut self, name: &str) -> Result<Node, String> {
+        let &(_, function, min, max) = FUNCTIONS
+            .iter()
+            .find(|(function, ..)| function.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown function {}.", name))?;
+        let mut arguments = vec![];
+        if self.accept(&[')']).is_none() {
+            loop {
+                arguments.push(self.join()?);
+                if self.accept(&[')']).is_some() {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        match (min..=max).contains(&arguments.len()) {
+            true => Ok(Node::Call(function, arguments)),
+            false => Err(format!("Wrong number of arguments for {}.", name)),
+        }
+    }
+}
+
+struct Context<'a> {
+    record: &'a StringRecord,
+    date_patterns: &'a [Vec<String>],
+    general_patterns: &'a [String],
+}
+
+impl Context<'_> {
+    fn evaluate(&self, node: &Node) -> Option<Datum> {
+        Some(match node {
+            Node::Text(text) => Datum::Text(text.clone()),
+            Node::Number(number) => Datum::Number(*number),
+            Node::Column(col) => Datum::Text(self.record.get(*col).unwrap_or_default().to_string()),
+            Node::Negate(node) => Datum::Number(-self.number(node)?),
+            Node::Binary(Operator::Join, left, right) => {
+                Datum::Text(self.text(left)? + &self.text(right)?)
+            }
+            Node::Binary(operator, left, right) => {
+                let (left, right) = (self.number(left)?, self.number(right)?);
+                Datum::Number(match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide if right == 0.0 => return None,
+                    Operator::Divide => left / right,
+                    Operator::Join => unreachable!(),
+                })
+            }
+            Node::Call(function, arguments) => self.call(*function, arguments)?,
+        })
+    }
+
+    fn text(&self, node: &Node) -> Option<String> {
+        Some(match self.evaluate(node)? {
+            Datum::Text(text) => text,
+            Datum::Number(number) => format_number(number),
+            Datum::Date(date) => date.to_iso(),
+        })
+    }
+
+    fn number(&self, node: &Node) -> Option<f64> {
+        match self.evaluate(node)? {
+            Datum::Number(number) => Some(number),
+            Datum::Text(text) => parse_number(&text),
+            Datum::Date(_) => None,
+        }
+    }
+
+    /// Columns are parsed with their own date patterns, other text with the general ones.
+    fn date(&self, node: &Node) -> Option<ParsedTime> {
+        if let Node::Column(col) = node {
+            let field = self.record.get(*col)?;
+            return try_parse_time(field, self.date_patterns.get(*col)?).ok();
+        }
+        match self.evaluate(node)? {
+            Datum::Date(date) => Some(date),
+            Datum::Text(text) => try_parse_time(&text, self.general_patterns).ok(),
+            Datum::Number(_) => None,
+        }
+    }
+
+    fn call(&self, function: Function, arguments: &[Node]) -> Option<Datum> {
+        let text = |idx: usize| self.text(&arguments[idx]);
+        let number = |idx: usize| self.number(&arguments[idx]);
+        let count = |idx: usize| number(idx).filter(|n| *n >= 0.0).map(|n| n as usize);
+        let numbers = || arguments.iter().map(|node| self.number(node));
+        Some(match function {
+            Function::Upper => Datum::Text(text(0)?.to_uppercase()),
+            Function::Lower => Datum::Text(text(0)?.to_lowercase()),
+            Function::Trim => Datum::Text(text(0)?.trim().to_string()),
+            Function::Len => Datum::Number(text(0)?.chars().count() as f64),
+            Function::Left => Datum::Text(text(0)?.chars().take(count(1)?).collect()),
+            Function::Right => {
+                let chars: Vec<char> = text(0)?.chars().collect();
+                let start = chars.len().saturating_sub(count(1)?);
+                Datum::Text(chars[start..].iter().collect())
+            }
+            Function::Mid => Datum::Text(
+                text(0)?
+                    .chars()
+                    .skip(count(1)?.max(1) - 1)
+                    .take(count(2)?)
+                    .collect(),
+            ),
+            Function::Replace => Datum::Text(text(0)?.replace(&text(1)?, &text(2)?)),
+            Function::Concat => Datum::Text(
+                arguments
+                    .iter()
+                    .map(|node| self.text(node))
+                    .collect::<Option<String>>()?,
+            ),
+            Function::Coalesce => {
+                return arguments
+                    .iter()
+                    .filter_map(|node| self.evaluate(node))
+                    .find(|datum| !matches!(datum, Datum::Text(text) if text.trim().is_empty()))
+            }
+            Function::Round => {
+                let factor = 10f64.powi(match arguments.len() {
+                    2 => number(1)? as i32,
+                    _ => 0,
+                });
+                Datum::Number((number(0)? * factor).round() / factor)
+            }
+            Function::Abs => Datum::Number(number(0)?.abs()),
+            Function::Floor => Datum::Number(number(0)?.floor()),
+            Function::Ceil => Datum::Number(number(0)?.ceil()),
+            Function::Min => Datum::Number(
+                numbers()
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .reduce(f64::min)?,
+            ),
+            Function::Max => Datum::Number(
+                numbers()
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .reduce(f64::max)?,
+            ),
+            Function::Year => Datum::Number(date_part(self.date(&arguments[0])?).year() as f64),
+            Function::Month => Datum::Number(date_part(self.date(&arguments[0])?).month() as f64),
+            Function::Day => Datum::Number(date_part(self.date(&arguments[0])?).day() as f64),
+            Function::Date => Datum::Date(try_parse_time(&text(0)?, &[text(1)?]).ok()?),
+            Function::Format => {
+                let pattern = text(1)?;
+                if !is_valid_date_pattern(&pattern) {
+                    return None;
+                }
+                // the values are naive, patterns with an offset give no value
+                Datum::Text(try_format(match self.date(&arguments[0])? {
+                    ParsedTime::Date(date) => date.and_hms_opt(0, 0, 0)?.format(&pattern),
+                    ParsedTime::DateTime(datetime) => datetime.format(&pattern),
+                })?)
+            }
+        })
+    }
+}
+
+fn date_part(time: ParsedTime) -> NaiveDate {
+    match time {
+        ParsedTime::Date(date) => date,
+        ParsedTime::DateTime(datetime) => datetime.date(),
+    }
+}
+
+/// Whole numbers without fraction, others with up to 10 fraction digits.
+fn format_number(number: f64) -> String {
+    let text = format!("{:.10}", number);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Evaluates `source` on one record, every column reads ISO dates.
+    fn eval(source: &str, headers: &[&str], fields: &[&str]) -> String {
+        let headers = StringRecord::from(headers.to_vec());
+        let date_patterns =
+            vec![vec!["%Y-%m-%d".to_string(), "%Y-%m-%d %H:%M".to_string()]; headers.len()];
+        Expression::parse(source, &headers).unwrap().evaluate(
+            &StringRecord::from(fields.to_vec()),
+            &date_patterns,
+            &["%d.%m.%Y".to_string()],
+        )
+    }
+
+    fn parse_error(source: &str) -> String {
+        Expression::parse(source, &StringRecord::from(vec!["Name"])).unwrap_err()
+    }
+
+    #[test]
+    fn arithmetic_precedence() {
+        assert_eq!(eval("1 + 2 * 3", &[], &[]), "7");
+        assert_eq!(eval("(1 + 2) * 3", &[], &[]), "9");
+        assert_eq!(eval("10 - 4 - 3", &[], &[]), "3");
+        assert_eq!(eval("-2 * -3 / 4", &[], &[]), "1.5");
+        assert_eq!(eval("1 / 0", &[], &[]), "");
+        assert_eq!(eval("1 + 2 & \"x\"", &[], &[]), "3x");
+    }
+
+    #[test]
+    fn columns_in_both_notations() {
+        let headers = ["Amount", "Unit Price", "Name"];
+        let fields = ["1.234,5", "2.5", "Anna"];
+        assert_eq!(eval("Amount * 2", &headers, &fields), "2469");
+        assert_eq!(eval("-[Unit Price] + 1", &headers, &fields), "-1.5");
+        assert_eq!(eval("Name + 1", &headers, &fields), "");
+        assert_eq!(
+            eval("Name & \" said \"\"hi\"\"\"", &headers, &fields),
+            "Anna said \"hi\""
+        );
+    }
+
+    #[test]
+    fn text_functions() {
+        let headers = ["Name", "Empty"];
+        let fields = [" Anna Lena ", " "];
+        assert_eq!(eval("upper(trim(Name))", &headers, &fields), "ANNA LENA");
+        assert_eq!(eval("len(Name)", &headers, &fields), "11");
+        assert_eq!(eval("left(trim(Name), 4)", &headers, &fields), "Anna");
+        assert_eq!(eval("right(trim(Name), 4)", &headers, &fields), "Lena");
+        assert_eq!(eval("mid(trim(Name), 6, 2)", &headers, &fields), "Le");
+        assert_eq!(
+            eval("replace(Name, \" \", \"_\")", &headers, &fields),
+            "_Anna_Lena_"
+        );
+        assert_eq!(eval("concat(\"a\", 1, \"b\")", &headers, &fields), "a1b");
+        assert_eq!(eval("coalesce(Empty, \"none\")", &headers, &fields), "none");
+    }
+
+    #[test]
+    fn number_and_date_functions() {
+        let headers = ["Day", "Text"];
+        let fields = ["2024-03-05", "5.3.2024"];
+        assert_eq!(eval("round(2.3456, 2)", &headers, &fields), "2.35");
+        assert_eq!(
+            eval(
+                "round(2.5) + abs(-1) + floor(1.9) + ceil(1.1)",
+                &headers,
+                &fields
+            ),
+            "7"
+        );
+        assert_eq!(eval("min(3, 1, 2) & max(3, 1, 2)", &headers, &fields), "13");
+        assert_eq!(
+            eval("year(Day) * 100 + month(Day)", &headers, &fields),
+            "202403"
+        );
+        assert_eq!(eval("day(Text)", &headers, &fields), "");
+        assert_eq!(
+            eval("day(date(Text, \"%d.%m.%Y\"))", &headers, &fields),
+            "5"
+        );
+        assert_eq!(
+            eval("date(Text, \"%d.%m.%Y\")", &headers, &fields),
+            "2024-03-05"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_error("1 +"), "Unexpected end.");
+        assert_eq!(parse_error("1 2"), "Unexpected number 2.");
+        assert_eq!(parse_error("(1"), "Expected ')'.");
+        assert_eq!(parse_error("\"open"), "Missing '\"'.");
+        assert_eq!(parse_error("[Name"), "Missing ']'.");
+        assert_eq!(parse_error("Name # 1"), "Unexpected '#'.");
+        assert_eq!(parse_error("FirstName"), "Unknown column FirstName.");
+        assert_eq!(parse_error("nope(1)"), "Unknown function nope.");
+        assert_eq!(
+            parse_error("left(Name)"),
+            "Wrong number of arguments for left."
+        );
+    }
+
+    #[test]
+    fn derived_columns_use_earlier_ones() {
+        let headers = StringRecord::from(vec!["Price", "Count"]);
+        let derived = vec![
+            ("Total".to_string(), "Price * Count".to_string()),
+            ("Label".to_string(), "Total & \" EUR\"".to_string()),
+        ];
+        let (headers, expressions) = parse_derived_columns(&headers, &derived).unwrap();
+        assert_eq!(
+            headers,
+            StringRecord::from(vec!["Price", "Count", "Total", "Label"])
+        );
+
+        let mut record = StringRecord::from(vec!["2,5", "4"]);
+        for expression in expressions.iter() {
+            let field = expression.evaluate(&record, &vec![vec![]; 4], &[]);
+            record.push_field(&field);
+        }
+        assert_eq!(record, StringRecord::from(vec!["2,5", "4", "10", "10 EUR"]));
+
+        let duplicate = vec![("Price".to_string(), "1".to_string())];
+        assert!(parse_derived_columns(&headers, &duplicate).is_err());
+        let unknown = vec![("Later".to_string(), "Label2".to_string())];
+        assert_eq!(
+            parse_derived_columns(&headers, &unknown).unwrap_err(),
+            "Later: Unknown column Label2."
+        );
+    }
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(
+            eval("format(Day, \"%d.%m.%Y\")", &["Day"], &["2024-03-05"]),
+            "05.03.2024"
+        );
+        assert_eq!(
+            eval("format(Day, \"%H:%M\")", &["Day"], &["2024-03-05 10:30"]),
+            "10:30"
+        );
+        assert_eq!(eval("format(Day, \"%Y\")", &["Day"], &["no date"]), "");
+    }
+
+    #[test]
+    fn format_without_offset_is_empty() {
+        assert_eq!(eval("format(Day, \"%z\")", &["Day"], &["2024-03-05"]), "");
+        assert_eq!(
+            eval("format(Day, \"%+\")", &["Day"], &["2024-03-05 10:30"]),
+            ""
+        );
+        assert_eq!(eval("format(Day, \"%Q\")", &["Day"], &["2024-03-05"]), "");
+    }
+}
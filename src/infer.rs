@@ -0,0 +1,402 @@
+use csv::StringRecord;
+
+use crate::utils::{try_parse_time, ParsedTime};
+
+/// Excel stores numbers as doubles, anything with more digits would lose precision.
+const MAX_DIGITS: usize = 15;
+
+const TRUE_VALUES: [&str; 2] = ["true", "wahr"];
+const FALSE_VALUES: [&str; 2] = ["false", "falsch"];
+
+/// Decimal separator of a number column. Thousands are grouped by the other one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Separator {
+    /// German notation `1.234,56`
+    Comma,
+    /// English notation `1,234.56`
+    Point,
+}
+
+/// Type of a column derived from all of its non empty values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    Text,
+    Integer,
+    /// Scale is the highest number of fraction digits found in the column.
+    Decimal {
+        separator: Separator,
+        scale: usize,
+    },
+    /// Values like `12,5 %`, stored as fraction (0.125) in Excel.
+    Percentage {
+        separator: Separator,
+        scale: usize,
+    },
+    Boolean,
+    /// Every value is a date from [`try_parse_time`], without time part.
+    Date,
+    /// Every value is a date from [`try_parse_time`], some with time part.
+    DateTime,
+}
+
+/// Typed representation of a single field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    Text(&'a str),
+    Number(f64),
+    Boolean(bool),
+    Date(ParsedTime),
+}
+
+/// Types and date patterns of all columns.
+#[derive(Clone)]
+pub struct Schema {
+    pub column_types: Vec<ColumnType>,
+    pub date_patterns: Vec<Vec<String>>,
+}
+
+impl Schema {
+    /// Typed value of a field. Dates are recognized independent of the column type.
+    pub fn value<'a>(&self, col: usize, field: &'a str) -> Value<'a> {
+        if let Ok(parsed) = try_parse_time(field, &self.date_patterns[col]) {
+            return Value::Date(parsed);
+        }
+        self.column_type(col).parse(field)
+    }
+
+    pub fn column_type(&self, col: usize) -> ColumnType {
+        self.column_types
+            .get(col)
+            .copied()
+            .unwrap_or(ColumnType::Text)
+    }
+}
+
+impl ColumnType {
+    /// Converts a field into its typed value. Falls back to text if the field doesn't fit.
+    pub fn parse(self, field: &str) -> Value<'_> {
+        let parsed = match self {
+            ColumnType::Text | ColumnType::Date | ColumnType::DateTime => None,
+            ColumnType::Integer => parse_integer(field),
+            ColumnType::Decimal { separator, .. } => {
+                parse_decimal(field, separator).map(|(number, _)| number)
+            }
+            ColumnType::Percentage { separator, .. } => {
+                parse_percentage(field, separator).map(|(number, _)| number)
+            }
+            ColumnType::Boolean => {
+                return parse_boolean(field).map_or(Value::Text(field), Value::Boolean)
+            }
+        };
+        parsed.map_or(Value::Text(field), Value::Number)
+    }
+
+    /// Excel number format for the column, `None` for non numeric columns.
+    pub fn num_format(self) -> Option<String> {
+        match self {
+            ColumnType::Integer => Some("0".to_string()),
+            ColumnType::Decimal { scale, .. } => Some(with_scale("#,##0", scale)),
+            ColumnType::Percentage { scale, .. } => Some(with_scale("0", scale) + "%"),
+            _ => None,
+        }
+    }
+}
+
+fn with_scale(format: &str, scale: usize) -> String {
+    match scale {
+        0 => format.to_string(),
+        _ => format!("{}.{}", format, "0".repeat(scale)),
+    }
+}
+
+/// Tracks which types are still possible for a column while scanning the records.
+///
+/// Decimal candidates hold the highest scale seen so far.
+struct Candidates {
+    seen: bool,
+    /// Whether a date with time part has been seen, `None` if a value isn't a date.
+    date: Option<bool>,
+    integer: bool,
+    boolean: bool,
+    decimal: [Option<usize>; 2],
+    percentage: [Option<usize>; 2],
+}
+
+const SEPARATORS: [Separator; 2] = [Separator::Comma, Separator::Point];
+
+impl Candidates {
+    fn new() -> Candidates {
+        Candidates {
+            seen: false,
+            date: Some(false),
+            integer: true,
+            boolean: true,
+            decimal: [Some(0); 2],
+            percentage: [Some(0); 2],
+        }
+    }
+
+    fn update(&mut self, field: &str, date_patterns: &[String]) {
+        if field.trim().is_empty() {
+            return;
+        }
+        self.seen = true;
+        if let Some(time) = self.date {
+            self.date = match try_parse_time(field, date_patterns) {
+                Ok(ParsedTime::Date(_)) => Some(time),
+                Ok(ParsedTime::DateTime(_)) => Some(true),
+                Err(_) => None,
+            };
+        }
+        self.integer = self.integer && parse_integer(field).is_some();
+        self.boolean = self.boolean && parse_boolean(field).is_some();
+        for (idx, &separator) in SEPARATORS.iter().enumerate() {
+            self.decimal[idx] = max_scale(self.decimal[idx], parse_decimal(field, separator));
+            self.percentage[idx] =
+                max_scale(self.percentage[idx], parse_percentage(field, separator));
+        }
+    }
+
+    /// Picks the most specific type. German notation wins over English if both fit.
+    fn result(&self) -> ColumnType {
+        if !self.seen {
+            return ColumnType::Text;
+        }
+        match self.date {
+            Some(true) => return ColumnType::DateTime,
+            Some(false) => return ColumnType::Date,
+            None => (),
+        }
+        if self.integer {
+            return ColumnType::Integer;
+        }
+        if self.boolean {
+            return ColumnType::Boolean;
+        }
+        for (idx, &separator) in SEPARATORS.iter().enumerate() {
+            if let Some(scale) = self.decimal[idx] {
+                return ColumnType::Decimal { separator, scale };
+            }
+        }
+        for (idx, &separator) in SEPARATORS.iter().enumerate() {
+            if let Some(scale) = self.percentage[idx] {
+                return ColumnType::Percentage { separator, scale };
+            }
+        }
+        ColumnType::Text
+    }
+}
+
+fn max_scale(current: Option<usize>, parsed: Option<(f64, usize)>) -> Option<usize> {
+    match (current, parsed) {
+        (Some(current), Some((_, scale))) => Some(current.max(scale)),
+        _ => None,
+    }
+}
+
+/// Infers the type of every column from records without header.
+///
+/// Dates are recognized with the patterns of the column.
+pub fn infer_column_types<'a>(
+    date_patterns: &[Vec<String>],
+    records: impl Iterator<Item = &'a StringRecord>,
+) -> Vec<ColumnType> {
+    let mut candidates: Vec<Candidates> = date_patterns.iter().map(|_| Candidates::new()).collect();
+    for record in records {
+        for ((candidate, field), patterns) in candidates
+            .iter_mut()
+            .zip(record.iter())
+            .zip(date_patterns.iter())
+        {
+            candidate.update(field, patterns);
+        }
+    }
+    candidates.iter().map(Candidates::result).collect()
+}
+
+/// Number in any notation known to the inference, German notation is tried first.
+pub fn parse_number(field: &str) -> Option<f64> {
+    parse_integer(field).or_else(|| {
+        SEPARATORS.iter().find_map(|&separator| {
+            parse_decimal(field, separator)
+                .or_else(|| parse_percentage(field, separator))
+                .map(|(number, _)| number)
+        })
+    })
+}
+
+/// Splits an optional sign from the digits.
+fn split_sign(field: &str) -> (f64, &str) {
+    match field.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, field.strip_prefix('+').unwrap_or(field)),
+    }
+}
+
+/// Leading zeros mark identifiers like `007` which must be kept as text.
+fn is_plain_digits(digits: &str) -> bool {
+    !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+        && !(digits.len() > 1 && digits.starts_with('0'))
+}
+
+fn parse_integer(field: &str) -> Option<f64> {
+    let (_, digits) = split_sign(field.trim());
+    if !is_plain_digits(digits) || digits.len() > MAX_DIGITS {
+        return None;
+    }
+    field.trim().parse::<i64>().ok().map(|number| number as f64)
+}
+
+/// Parses numbers like `-1.234,56` (comma) or `1,234.56` (point).
+///
+/// Returns the number and its count of fraction digits.
+fn parse_decimal(field: &str, separator: Separator) -> Option<(f64, usize)> {
+    let (decimal, thousands) = match separator {
+        Separator::Comma => (',', '.'),
+        Separator::Point => ('.', ','),
+    };
+    let (sign, unsigned) = split_sign(field.trim());
+    let (integer_part, fraction) = match unsigned.split_once(decimal) {
+        Some((integer_part, fraction)) => (integer_part, fraction),
+        None => (unsigned, ""),
+    };
+    if !fraction.chars().all(|c| c.is_ascii_digit())
+        || (fraction.is_empty() && unsigned.ends_with(decimal))
+    {
+        return None;
+    }
+
+    let mut groups = integer_part.split(thousands);
+    let first = groups.next()?;
+    let mut digits = first.to_string();
+    for group in groups {
+        if first.len() > 3 || group.len() != 3 || !group.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        digits.push_str(group);
+    }
+    if !is_plain_digits(first) || digits.len() + fraction.len() > MAX_DIGITS {
+        return None;
+    }
+
+    let number: f64 = format!("{}.{}", digits, fraction)
+        .trim_end_matches('.')
+        .parse()
+        .ok()?;
+    Some((sign * number, fraction.len()))
+}
+
+/// Parses `12,5 %` into `0.125`. The scale refers to the displayed percentage.
+fn parse_percentage(field: &str, separator: Separator) -> Option<(f64, usize)> {
+    let number = field.trim().strip_suffix('%')?.trim_end();
+    parse_decimal(number, separator).map(|(number, scale)| (number / 100.0, scale))
+}
+
+fn parse_boolean(field: &str) -> Option<bool> {
+    let field = field.trim().to_lowercase();
+    if TRUE_VALUES.contains(&field.as_str()) {
+        Some(true)
+    } else if FALSE_VALUES.contains(&field.as_str()) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::DEFAULT_DATE_PATTERNS;
+
+    /// Type of a single column with the default date patterns.
+    fn infer(fields: &[&str]) -> ColumnType {
+        let records: Vec<StringRecord> = fields
+            .iter()
+            .map(|field| StringRecord::from(vec![*field]))
+            .collect();
+        let date_patterns = vec![DEFAULT_DATE_PATTERNS
+            .iter()
+            .map(|p| p.to_string())
+            .collect()];
+        infer_column_types(&date_patterns, records.iter())[0]
+    }
+
+    fn decimal(separator: Separator, scale: usize) -> ColumnType {
+        ColumnType::Decimal { separator, scale }
+    }
+
+    #[test]
+    fn integers_without_leading_zeros() {
+        assert_eq!(infer(&["12", "-3", "+4", ""]), ColumnType::Integer);
+        assert_eq!(infer(&["0", "10"]), ColumnType::Integer);
+        assert_eq!(infer(&["007", "12"]), ColumnType::Text);
+        assert_eq!(infer(&["0,5", "01,5"]), ColumnType::Text);
+    }
+
+    #[test]
+    fn at_most_15_digits() {
+        assert_eq!(infer(&["123456789012345"]), ColumnType::Integer);
+        assert_eq!(infer(&["1234567890123456"]), ColumnType::Text);
+        assert_eq!(
+            infer(&["1.234.567.890.123,45"]),
+            decimal(Separator::Comma, 2)
+        );
+        assert_eq!(infer(&["12.345.678.901.234,56"]), ColumnType::Text);
+        assert_eq!(infer(&["12345678901234,5"]), decimal(Separator::Comma, 1));
+    }
+
+    #[test]
+    fn german_and_english_notation() {
+        assert_eq!(
+            infer(&["1.234,56", "-7,25", "3"]),
+            decimal(Separator::Comma, 2)
+        );
+        assert_eq!(infer(&["1,234.5", "2.25"]), decimal(Separator::Point, 2));
+        // both fit, German wins
+        assert_eq!(infer(&["1.234"]), decimal(Separator::Comma, 0));
+        assert_eq!(infer(&["1.234", "0.5"]), decimal(Separator::Point, 3));
+        assert_eq!(infer(&["1,2", "1.5"]), ColumnType::Text);
+        assert_eq!(infer(&["1.23,4"]), ColumnType::Text);
+    }
+
+    #[test]
+    fn percentages_booleans_and_dates() {
+        assert_eq!(
+            infer(&["12,5 %", "3%"]),
+            ColumnType::Percentage {
+                separator: Separator::Comma,
+                scale: 1
+            }
+        );
+        assert_eq!(infer(&["true", "FALSCH", "Wahr"]), ColumnType::Boolean);
+        assert_eq!(infer(&["true", "1"]), ColumnType::Text);
+        assert_eq!(infer(&["1.3.2022", "2022-03-02"]), ColumnType::Date);
+        assert_eq!(
+            infer(&["1.3.2022", "2022-03-01T14:23:22"]),
+            ColumnType::DateTime
+        );
+        assert_eq!(infer(&["1.3.2022", "soon"]), ColumnType::Text);
+        assert_eq!(infer(&["", " "]), ColumnType::Text);
+    }
+
+    #[test]
+    fn parse_with_column_type() {
+        assert_eq!(
+            decimal(Separator::Point, 3).parse("1.234"),
+            Value::Number(1.234)
+        );
+        assert_eq!(
+            decimal(Separator::Comma, 0).parse("1.234"),
+            Value::Number(1234.0)
+        );
+        assert_eq!(ColumnType::Integer.parse("x"), Value::Text("x"));
+        assert_eq!(ColumnType::Boolean.parse("Falsch"), Value::Boolean(false));
+        assert_eq!(
+            decimal(Separator::Comma, 2).num_format().as_deref(),
+            Some("#,##0.00")
+        );
+        assert_eq!(parse_number("12,5 %"), Some(0.125));
+        assert_eq!(parse_number("1,234.56"), Some(1234.56));
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use csv::StringRecord;
+
+/// Reference rows by key, joined to the input records.
+///
+/// The delimiter of the reference file is `;` like for the input, or `,` if its header has
+/// no `;`. Keys are compared without surrounding whitespace, the first row of a key wins.
+pub struct Lookup {
+    /// Position of the key in the input records.
+    input_key: usize,
+    /// Reference header without the key column.
+    pub headers: StringRecord,
+    rows: HashMap<String, StringRecord>,
+    /// Keys without reference row with their number of rows, in input order.
+    unmatched: Vec<(String, usize)>,
+    unmatched_index: HashMap<String, usize>,
+}
+
+impl Lookup {
+    /// Reads the reference file, its columns must not clash with `input_headers`.
+    pub fn read(
+        path: &Path,
+        input_key: &str,
+        reference_key: &str,
+        input_headers: &StringRecord,
+    ) -> Result<Lookup, String> {
+        let input_key = input_headers
+            .iter()
+            .position(|header| header == input_key)
+            .ok_or_else(|| format!("Input has no column {}.", input_key))?;
+        let headers = read_reference_headers(path)?;
+        let key_column = key_position(&headers, reference_key)?;
+        let columns: Vec<usize> = (0..headers.len())
+            .filter(|&col| col != key_column)
+            .collect();
+        let headers: StringRecord = columns.iter().map(|&col| &headers[col]).collect();
+        if let Some(header) = headers
+            .iter()
+            .find(|header| input_headers.iter().any(|input| input == *header))
+        {
+            return Err(format!("Column {} exists in the input as well.", header));
+        }
+
+        let mut rows = HashMap::new();
+        let mut rdr = reader(path)?;
+        for record in rdr.records() {
+            let record = record.map_err(|error| error.to_string())?;
+            let key = record
+                .get(key_column)
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            rows.entry(key).or_insert_with(|| {
+                columns
+                    .iter()
+                    .map(|&col| record.get(col).unwrap_or_default())
+                    .collect()
+            });
+        }
+        Ok(Lookup {
+            input_key,
+            headers,
+            rows,
+            unmatched: vec![],
+            unmatched_index: HashMap::new(),
+        })
+    }
+
+    /// Appends the reference fields of the record's key, empty fields if there is no
+    /// reference row.
+    ///
+    /// Returns whether the key matched.
+    pub fn join(&self, record: &mut StringRecord) -> bool {
+        match self
+            .rows
+            .get(record.get(self.input_key).unwrap_or_default().trim())
+        {
+            Some(row) => {
+                row.iter().for_each(|field| record.push_field(field));
+                true
+            }
+            None => {
+                (0..self.headers.len()).for_each(|_| record.push_field(""));
+                false
+            }
+        }
+    }
+
+    /// Counts a record whose key has no reference row.
+    pub fn add_unmatched(&mut self, record: &StringRecord) {
+        let key = record.get(self.input_key).unwrap_or_default().trim();
+        match self.unmatched_index.get(key) {
+            Some(&idx) => self.unmatched[idx].1 += 1,
+            None => {
+                self.unmatched_index
+                    .insert(key.to_string(), self.unmatched.len());
+                self.unmatched.push((key.to_string(), 1));
+            }
+        }
+    }
+
+    pub fn unmatched(self) -> Vec<(String, usize)> {
+        self.unmatched
+    }
+}
+
+/// Header of a reference file, for the wizard.
+pub fn read_reference_headers(path: &Path) -> Result<StringRecord, String> {
+    reader(path)?
+        .headers()
+        .cloned()
+        .map_err(|error| error.to_string())
+}
+
+/// Columns added by the join, the reference header without its key column.
+pub fn joined_columns(path: &Path, reference_key: &str) -> Result<StringRecord, String> {
+    let headers = read_reference_headers(path)?;
+    let key_column = key_position(&headers, reference_key)?;
+    Ok(headers
+        .iter()
+        .enumerate()
+        .filter(|(col, _)| *col != key_column)
+        .map(|(_, header)| header)
+        .collect())
+}
+
+fn key_position(headers: &StringRecord, reference_key: &str) -> Result<usize, String> {
+    headers
+        .iter()
+        .position(|header| header == reference_key)
+        .ok_or_else(|| format!("Reference file has no column {}.", reference_key))
+}
+
+fn reader(path: &Path) -> Result<csv::Reader<File>, String> {
+    let mut first_line = String::new();
+    File::open(path)
+        .and_then(|file| BufReader::new(file).read_line(&mut first_line))
+        .map_err(|error| error.to_string())?;
+    let delimiter = match first_line.contains(';') {
+        true => b';',
+        false => b',',
+    };
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    fn reference(name: &str, content: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("csv-helper-{}-{}.csv", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn joins_by_trimmed_key_and_counts_unmatched() {
+        let path = reference(
+            "lookup",
+            "Region,Id,Manager\nNord, 1 ,Anna\nSüd,2,Ben\nOst,1,Carla\n",
+        );
+        let input_headers = StringRecord::from(vec!["Customer", "Name"]);
+        let mut lookup = Lookup::read(&path, "Customer", "Id", &input_headers).unwrap();
+        assert_eq!(
+            lookup.headers,
+            StringRecord::from(vec!["Region", "Manager"])
+        );
+
+        let mut matched = StringRecord::from(vec!["1 ", "x"]);
+        assert!(lookup.join(&mut matched));
+        assert_eq!(matched, StringRecord::from(vec!["1 ", "x", "Nord", "Anna"]));
+
+        for key in ["3", "", "3"].iter() {
+            let mut record = StringRecord::from(vec![*key, "x"]);
+            if !lookup.join(&mut record) {
+                assert_eq!(record, StringRecord::from(vec![*key, "x", "", ""]));
+                lookup.add_unmatched(&record);
+            }
+        }
+        assert_eq!(
+            lookup.unmatched(),
+            vec![("3".to_string(), 2), (String::new(), 1)]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rejects_missing_and_clashing_columns() {
+        let path = reference("lookup-errors", "Id;Name;Manager\n1;Anna;Ben\n");
+        let input_headers = StringRecord::from(vec!["Customer", "Name"]);
+        let error = |input_key: &str, reference_key: &str| {
+            Lookup::read(&path, input_key, reference_key, &input_headers)
+                .err()
+                .unwrap()
+        };
+        assert_eq!(error("Kunde", "Id"), "Input has no column Kunde.");
+        assert_eq!(error("Customer", "ID"), "Reference file has no column ID.");
+        assert_eq!(
+            error("Customer", "Id"),
+            "Column Name exists in the input as well."
+        );
+        assert_eq!(
+            joined_columns(&path, "Name").unwrap(),
+            StringRecord::from(vec!["Id", "Manager"])
+        );
+        fs::remove_file(path).unwrap();
+    }
+}
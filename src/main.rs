@@ -1,22 +1,67 @@
+mod archive;
+mod chunk;
+mod clean;
+mod columnar;
+mod crosstab;
+mod dedup;
 mod errors;
+mod expr;
+mod infer;
+mod lookup;
+mod manifest;
+mod mask;
+mod naming;
+mod sort;
+mod staging;
+mod stats;
 mod transform;
 mod utils;
 
 use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use chunk::parse_size;
+use clean::{clean, column_steps, Step, CLEANING_HELP};
 use csv::StringRecord;
 use cursive::{
     align::HAlign,
     theme::Effect,
     traits::{Nameable, Resizable, Scrollable},
-    views::{Dialog, DialogFocus, DummyView, LinearLayout, Panel, SelectView, TextArea, TextView},
+    views::{
+        Checkbox, Dialog, DialogFocus, DummyView, EditView, LinearLayout, Panel, SelectView,
+        TextArea, TextView,
+    },
     Cursive,
 };
-use transform::{Options, Transformer};
-use utils::{get_headers_from_file, select_directory, select_file};
+use expr::{parse_derived_columns, EXPRESSION_HELP};
+use lookup::{joined_columns, read_reference_headers, Lookup};
+use mask::{parse_masks, Mask, MASKING_HELP};
+use naming::validate;
+use stats::{format_number, format_value};
+use transform::{
+    ArchiveMode, ChunkMode, CsvDialect, DedupScope, ExistingPolicy, FormulaPolicy, Options,
+    OutputFormat, Summary, Transformer, ARCHIVE_MODES, CHUNK_MODES, DEDUP_SCOPES,
+    EXISTING_POLICIES, FORMULA_POLICIES, JOIN_KINDS, OUTPUT_FORMATS, PARQUET_COMPRESSIONS,
+    SQLITE_LAYOUTS,
+};
+use utils::{
+    get_headers_from_file, read_header_renames, read_sample, select_directory, select_file,
+    CSV_DELIMITERS, CSV_LINE_ENDINGS, CSV_QUOTE_STYLES, EXCEL_DATETIME_FORMATS, EXCEL_DATE_FORMATS,
+    EXCEL_TABLE_STYLES,
+};
+
+const NO_TABLE: &str = "No table";
+const NO_CROSSTAB: &str = "No crosstab";
+const COUNT_ROWS: &str = "Count rows";
+
+/// Rows read for the cleaning preview and values shown per column.
+const PREVIEW_ROWS: usize = 1000;
+const PREVIEW_VALUES: usize = 5;
 
 fn main() {
     // Creates the cursive root - required for every application.
@@ -42,13 +87,15 @@ fn select_file_and_directory_display(siv: &mut Cursive, file_paths: Option<(Stri
         LinearLayout::vertical()
             .child(DummyView)
             .child(TextView::new(
-                "Split CSV-file by category and filter (optional).\nOutput files are in format CSV and Excel.",
+                "Split CSV-file by category and filter (optional).\nOutput files are in format CSV, Excel, JSON, NDJSON, Parquet, Arrow or a SQLite database.",
             ))
             .child(DummyView)
             .child(TextView::new("Note:"))
-            .child(TextView::new("Dates are automatically recognized with following format: \"d.m.yyyy hh:mm:ss\""))
-            .child(TextView::new("For example: \"1.3.2022 14:23:22\""))
+            .child(TextView::new("Dates are automatically recognized, by default in German and ISO 8601 format."))
+            .child(TextView::new("For example: \"1.3.2022 14:23:22\", \"1.3.2022\" or \"2022-03-01T14:23:22\""))
             .child(TextView::new("This will allow dates to be correctly formatted in excel files."))
+            .child(TextView::new("Further patterns can be added in the output settings."))
+            .child(TextView::new("Numbers, percentages and booleans are detected per column as well."))
             .child(DummyView)
             .child(
                 LinearLayout::horizontal()
@@ -90,33 +137,371 @@ fn select_file_and_directory_display(siv: &mut Cursive, file_paths: Option<(Stri
             return s.add_layer(Dialog::info("Input or output missing."));
         }
 
-        select_category_display(s, input_path.source().to_string(), output_path.source().to_string())
+        let options = Options::new(
+            String::new(),
+            PathBuf::from(input_path.source()),
+            PathBuf::from(output_path.source()),
+            None,
+        );
+        cleaning_display(s, options)
     }
     )
     .button("Quit", |s| s.quit());
     siv.add_layer(select_file_and_directory_dialog);
 }
 
+/// Input and output path for the start screen.
+fn file_paths(options: &Options) -> Option<(String, String)> {
+    Some((
+        options.input.to_string_lossy().to_string(),
+        options.output.to_string_lossy().to_string(),
+    ))
+}
+
+/// Cleaning display
+///
+/// Steps applied to the input fields before anything else, e.g. to avoid bogus categories.
+fn cleaning_display(s: &mut Cursive, options: Options) {
+    let headers = match get_headers_from_file(&options.input) {
+        Ok(headers) => headers,
+        Err(error) => return error_display(s, error.to_string()),
+    };
+    let (back_options, preview_options, preview_headers) =
+        (options.clone(), options.clone(), headers.clone());
+
+    let mut cleaning_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Clean values (optional):").style(Effect::Bold))
+            .child(TextView::new(
+                "One rule per line, steps run in order before grouping and filtering.",
+            ))
+            .child(DummyView)
+            .child(TextView::new(CLEANING_HELP))
+            .child(DummyView)
+            .child(TextView::new(format!(
+                "Input columns: {}",
+                headers.iter().collect::<Vec<_>>().join(", ")
+            )))
+            .child(DummyView)
+            .child(
+                TextArea::new()
+                    .content(options.get_cleaning_rules().join("\n"))
+                    .with_name("cleaningRules")
+                    .min_height(4),
+            ),
+    )
+    .button("Back", move |s| {
+        select_file_and_directory_display(s, file_paths(&back_options))
+    })
+    .button("Preview", move |s| {
+        let rules = cleaning_rules(s);
+        let steps = match column_steps(&rules, &preview_headers) {
+            Ok(steps) => steps,
+            Err(error) => return s.add_layer(Dialog::info(error)),
+        };
+        match read_sample(&preview_options.input, PREVIEW_ROWS) {
+            Ok(records) => s.add_layer(
+                Dialog::around(
+                    TextView::new(cleaning_preview(&preview_headers, &steps, &records))
+                        .scrollable(),
+                )
+                .title("Preview")
+                .dismiss_button("Close"),
+            ),
+            Err(error) => s.add_layer(Dialog::info(error.to_string())),
+        }
+    })
+    .button("Next", move |s| {
+        let rules = cleaning_rules(s);
+        if let Err(error) = column_steps(&rules, &headers) {
+            return s.add_layer(Dialog::info(error));
+        }
+        let mut options = options.clone();
+        options.set_cleaning_rules(rules);
+        lookup_display(s, options)
+    })
+    .title("Configuration");
+
+    cleaning_dialog.set_focus(DialogFocus::Button(2));
+
+    s.pop_layer();
+    s.add_layer(cleaning_dialog);
+}
+
+fn cleaning_rules(s: &mut Cursive) -> Vec<String> {
+    s.call_on_name("cleaningRules", |view: &mut TextArea| {
+        view.get_content()
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect()
+    })
+    .unwrap()
+}
+
+/// Distinct values of the sample changed by the steps, with the result of every step.
+fn cleaning_preview(
+    headers: &StringRecord,
+    steps: &[Vec<Step>],
+    records: &[StringRecord],
+) -> String {
+    let mut preview = vec![];
+    for ((col, header), steps) in headers.iter().enumerate().zip(steps) {
+        if steps.is_empty() {
+            continue;
+        }
+        preview.push(format!("{}:", header));
+        let mut shown = vec![];
+        for field in records.iter().filter_map(|record| record.get(col)) {
+            if shown.len() == PREVIEW_VALUES
+                || shown.contains(&field)
+                || clean(field, steps) == field
+            {
+                continue;
+            }
+            shown.push(field);
+            let mut value = field.to_string();
+            let mut line = format!("  {:?}", value);
+            for step in steps {
+                value = step.apply(&value);
+                line.push_str(&format!(" -> {}: {:?}", step.label(), value));
+            }
+            preview.push(line);
+        }
+        if shown.is_empty() {
+            preview.push(format!("  No changes in the first {} rows.", records.len()));
+        }
+    }
+    match preview.is_empty() {
+        true => "No cleaning rules.".to_string(),
+        false => preview.join("\n"),
+    }
+}
+
+/// Lookup display
+///
+/// Joins a reference CSV by a key column, e.g. the sales rep of a customer number.
+fn lookup_display(s: &mut Cursive, options: Options) {
+    let headers = match get_headers_from_file(&options.input) {
+        Ok(headers) => headers,
+        Err(error) => return error_display(s, error.to_string()),
+    };
+    let back_options = options.clone();
+    let (file, input_key, reference_key, join) = options.get_lookup();
+    let input_keys: Vec<&str> = headers.iter().collect();
+    let joins: Vec<&str> = JOIN_KINDS.iter().map(|join| join.label()).collect();
+    let reference_keys = file
+        .as_ref()
+        .and_then(|file| read_reference_headers(file).ok())
+        .unwrap_or_default();
+    let reference_keys: Vec<&str> = reference_keys.iter().collect();
+
+    let mut lookup_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Lookup (optional):").style(Effect::Bold))
+            .child(TextView::new(
+                "Adds the columns of a reference CSV to the rows with the same key.",
+            ))
+            .child(DummyView)
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Reference file").fixed_width(18))
+                    .child(
+                        TextView::new(
+                            file.map_or(String::new(), |file| file.to_string_lossy().to_string()),
+                        )
+                        .with_name("lookupFile")
+                        .min_width(30),
+                    ),
+            )
+            .child(select_row(
+                "Input key",
+                &input_keys,
+                &input_key,
+                "lookupInputKey",
+            ))
+            .child(select_row(
+                "Reference key",
+                &reference_keys,
+                &reference_key,
+                "lookupReferenceKey",
+            ))
+            .child(select_row(
+                "Unmatched rows",
+                &joins,
+                join.label(),
+                "lookupJoin",
+            )),
+    )
+    .button("Back", move |s| cleaning_display(s, back_options.clone()))
+    .button("Reference file", |s| match select_file() {
+        Ok(file) => match read_reference_headers(&file) {
+            Ok(reference_headers) => {
+                s.call_on_name("lookupFile", |view: &mut TextView| {
+                    view.set_content(file.to_string_lossy())
+                });
+                s.call_on_name("lookupReferenceKey", |view: &mut SelectView| {
+                    view.clear();
+                    reference_headers
+                        .iter()
+                        .for_each(|header| view.add_item(header.to_string(), header.to_string()));
+                });
+            }
+            Err(error) => s.add_layer(Dialog::info(error)),
+        },
+        Err(error) => s.add_layer(Dialog::info(error)),
+    })
+    .button("Remove", |s| {
+        s.call_on_name("lookupFile", |view: &mut TextView| view.set_content(""));
+        s.call_on_name("lookupReferenceKey", |view: &mut SelectView| view.clear());
+    })
+    .button("Next", move |s| {
+        let file = s
+            .call_on_name("lookupFile", |view: &mut TextView| {
+                view.get_content().source().to_string()
+            })
+            .unwrap();
+        let input_key = selected_format(s, "lookupInputKey");
+        let reference_key = s
+            .call_on_name("lookupReferenceKey", |view: &mut SelectView| {
+                view.selection().map(|key| key.to_string())
+            })
+            .unwrap()
+            .unwrap_or_default();
+        let join = selected_format(s, "lookupJoin");
+        let join = *JOIN_KINDS
+            .iter()
+            .find(|join_kind| join_kind.label() == join)
+            .unwrap();
+
+        let file = match file.is_empty() {
+            true => None,
+            false => {
+                if let Err(error) =
+                    Lookup::read(Path::new(&file), &input_key, &reference_key, &headers)
+                {
+                    return s.add_layer(Dialog::info(error));
+                }
+                Some(PathBuf::from(file))
+            }
+        };
+        let mut options = options.clone();
+        options.set_lookup(file, input_key, reference_key, join);
+        derived_columns_display(s, options)
+    })
+    .title("Configuration");
+
+    lookup_dialog.set_focus(DialogFocus::Button(3));
+
+    s.pop_layer();
+    s.add_layer(lookup_dialog);
+}
+
+/// Input headers followed by the columns joined from the reference file.
+fn joined_headers(options: &Options) -> Result<StringRecord, String> {
+    let mut headers = get_headers_from_file(&options.input).map_err(|error| error.to_string())?;
+    if let (Some(file), _, reference_key, _) = options.get_lookup() {
+        headers.extend(joined_columns(&file, &reference_key)?.iter());
+    }
+    Ok(headers)
+}
+
+/// Derived columns display
+///
+/// Columns computed from expressions, available as category, filter and output column.
+fn derived_columns_display(s: &mut Cursive, options: Options) {
+    let headers = match joined_headers(&options) {
+        Ok(headers) => headers,
+        Err(error) => return error_display(s, error),
+    };
+    let back_options = options.clone();
+    let derived_columns = options.get_derived_columns();
+
+    let mut derived_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Derived columns (optional):").style(Effect::Bold))
+            .child(TextView::new(
+                "One per line, \"Name = expression\", later ones may use earlier ones.",
+            ))
+            .child(DummyView)
+            .child(TextView::new(EXPRESSION_HELP))
+            .child(DummyView)
+            .child(TextView::new(format!(
+                "Input columns: {}",
+                headers.iter().collect::<Vec<_>>().join(", ")
+            )))
+            .child(DummyView)
+            .child(
+                TextArea::new()
+                    .content(assignment_lines(&derived_columns))
+                    .with_name("derivedColumns")
+                    .min_height(4),
+            ),
+    )
+    .button("Back", move |s| lookup_display(s, back_options.clone()))
+    .button("Next", move |s| {
+        let content = s
+            .call_on_name("derivedColumns", |view: &mut TextArea| {
+                view.get_content().to_string()
+            })
+            .unwrap();
+        let mut derived_columns = vec![];
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match line.split_once('=') {
+                Some((name, expression)) => {
+                    derived_columns.push((name.trim().to_string(), expression.trim().to_string()))
+                }
+                None => {
+                    return s.add_layer(Dialog::info(format!(
+                        "\"{}\" is not \"Name = expression\".",
+                        line
+                    )))
+                }
+            }
+        }
+        if let Err(error) = parse_derived_columns(&headers, &derived_columns) {
+            return s.add_layer(Dialog::info(error));
+        }
+        let mut options = options.clone();
+        options.set_derived_columns(derived_columns);
+        select_category_display(s, options)
+    })
+    .title("Configuration");
+
+    derived_dialog.set_focus(DialogFocus::Button(1));
+
+    s.pop_layer();
+    s.add_layer(derived_dialog);
+}
+
 /// Select Category Display
 ///
 /// Reads file for headers. Allows user to select a category.
-fn select_category_display(s: &mut Cursive, input_path: String, output_path: String) {
+fn select_category_display(s: &mut Cursive, options: Options) {
     let mut select = SelectView::new()
         // Center the text horizontally
         .h_align(HAlign::Center)
         // Use keyboard to jump to the pressed letters
         .autojump();
 
-    let headers = match get_headers_from_file(&PathBuf::from(&input_path)) {
-        Ok(iter) => iter,
-        Err(error) => return error_display(s, error.to_string()),
+    let headers = match joined_headers(&options) {
+        Ok(headers) => headers,
+        Err(error) => return error_display(s, error),
+    };
+    let headers = match parse_derived_columns(&headers, &options.get_derived_columns()) {
+        Ok((headers, _)) => headers,
+        Err(error) => return error_display(s, error),
     };
 
     headers
         .iter()
         .for_each(|s| select.add_item(s.to_string(), s.to_string()));
 
-    let file_paths = Some((input_path.clone(), output_path.clone()));
+    let (back_options, none_options, none_headers) =
+        (options.clone(), options.clone(), headers.clone());
 
     s.pop_layer();
     s.add_layer(
@@ -129,12 +514,9 @@ fn select_category_display(s: &mut Cursive, input_path: String, output_path: Str
                     select
                         .on_submit(move |s, selected_category: &str| {
                             // Show a popup whenever the user presses <Enter>
-                            let options = Options::new(
-                                selected_category.to_string(),
-                                PathBuf::from(input_path.clone()),
-                                PathBuf::from(output_path.clone()),
-                                None,
-                            );
+                            let options = options
+                                .clone()
+                                .set_selected_category(selected_category.to_string());
 
                             select_filter_display(s, options, headers.clone());
                         })
@@ -143,7 +525,12 @@ fn select_category_display(s: &mut Cursive, input_path: String, output_path: Str
         )
         .title("Configuration")
         .button("Back", move |s| {
-            select_file_and_directory_display(s, file_paths.clone())
+            derived_columns_display(s, back_options.clone())
+        })
+        .button("No category", move |s| {
+            // Only split into parts, set on the output settings
+            let options = none_options.clone().set_selected_category(String::new());
+            select_filter_display(s, options, none_headers.clone())
         }),
     );
 }
@@ -167,7 +554,7 @@ fn select_filter_display(s: &mut Cursive, options: Options, headers: StringRecor
             .child(DummyView)
             .child(TextView::new(format!(
                 "Selected category: {}",
-                options.get_selected_category()
+                category_label(&options)
             )))
             .child(DummyView)
             .child(TextView::new("Select a filter (optional):").style(Effect::Bold))
@@ -187,14 +574,10 @@ fn select_filter_display(s: &mut Cursive, options: Options, headers: StringRecor
             ),
     )
     .button("Back", move |s| {
-        select_category_display(
-            s,
-            back_options.input.to_str().unwrap().to_string(),
-            back_options.output.to_str().unwrap().to_string(),
-        )
+        select_category_display(s, back_options.clone().set_filter(None))
     })
     .button("Next without filter", move |s| {
-        overview_display(s, skip_options.clone(), skip_headers.clone());
+        select_columns_display(s, skip_options.clone(), skip_headers.clone());
     })
     .button("Next with filter", move |s| {
         let mut options = options.clone();
@@ -208,7 +591,7 @@ fn select_filter_display(s: &mut Cursive, options: Options, headers: StringRecor
             })
             .unwrap(),
         )));
-        overview_display(s, options, headers.clone())
+        select_columns_display(s, options, headers.clone())
     })
     .title("Configuration");
 
@@ -218,13 +601,924 @@ fn select_filter_display(s: &mut Cursive, options: Options, headers: StringRecor
     s.add_layer(select_dialog);
 }
 
+/// Select columns display
+///
+/// Selected columns come first in their output order, the others follow in input order.
+fn select_columns_display(s: &mut Cursive, options: Options, headers: StringRecord) {
+    let (back_options, back_headers) = (options.clone(), headers.clone());
+    let (selected, drop_category_columns) = options.get_columns();
+    let mut columns: Vec<(String, bool)> = selected
+        .iter()
+        .map(|column| (column.clone(), true))
+        .collect();
+    let mut remaining = selected.clone();
+    for header in headers.iter() {
+        match remaining.iter().position(|column| column == header) {
+            Some(idx) => {
+                remaining.remove(idx);
+            }
+            None => columns.push((header.to_string(), selected.is_empty())),
+        }
+    }
+
+    let mut select = SelectView::new().on_submit(|s, _: &(String, bool)| toggle_column(s));
+    for (column, checked) in columns {
+        select.add_item(column_label(&column, checked), (column, checked));
+    }
+
+    let mut columns_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Select output columns:").style(Effect::Bold))
+            .child(TextView::new(
+                "<Enter> toggles a column, Up and Down change the order.",
+            ))
+            .child(DummyView)
+            .child(Panel::new(
+                select.with_name("columnsView").scrollable().max_height(12),
+            ))
+            .child(DummyView)
+            .child(checkbox_row(
+                "Drop category and filter columns",
+                drop_category_columns,
+                "dropCategoryColumns",
+            ))
+            .child(DummyView)
+            .child(TextView::new(
+                "Rename headers (one per line, \"Column = New name\"):",
+            ))
+            .child(
+                TextArea::new()
+                    .content(assignment_lines(&options.get_header_renames()))
+                    .with_name("headerRenames")
+                    .min_height(3),
+            )
+            .child(DummyView)
+            .child(TextView::new(
+                "Sort rows by (one column per line, \"Column desc\" for descending):",
+            ))
+            .child(
+                TextArea::new()
+                    .content(sort_lines(&options.get_sort_keys()))
+                    .with_name("sortKeys")
+                    .min_height(2),
+            ),
+    )
+    .button("Up", |s| move_column(s, -1))
+    .button("Down", |s| move_column(s, 1))
+    .button("Load renames", |s| match select_file() {
+        Ok(path) => match read_header_renames(&path) {
+            Ok(renames) => s
+                .call_on_name("headerRenames", |view: &mut TextArea| {
+                    view.set_content(assignment_lines(&renames))
+                })
+                .unwrap(),
+            Err(error) => s.add_layer(Dialog::info(error.to_string())),
+        },
+        Err(err) => s.add_layer(Dialog::info(err)),
+    })
+    .button("Back", move |s| {
+        select_filter_display(s, back_options.clone(), back_headers.clone())
+    })
+    .button("Next", move |s| {
+        let columns: Vec<String> = s
+            .call_on_name("columnsView", |view: &mut SelectView<(String, bool)>| {
+                view.iter()
+                    .filter(|(_, (_, checked))| *checked)
+                    .map(|(_, (column, _))| column.clone())
+                    .collect()
+            })
+            .unwrap();
+        if columns.is_empty() {
+            return s.add_layer(Dialog::info("Select at least one column."));
+        }
+
+        let mut header_renames = vec![];
+        let content = s
+            .call_on_name("headerRenames", |view: &mut TextArea| {
+                view.get_content().to_string()
+            })
+            .unwrap();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            match line.split_once('=') {
+                Some((from, to)) if headers.iter().any(|header| header == from.trim()) => {
+                    header_renames.push((from.trim().to_string(), to.trim().to_string()))
+                }
+                _ => {
+                    return s.add_layer(Dialog::info(format!(
+                        "\"{}\" is not \"Column = New name\" with an input column.",
+                        line
+                    )))
+                }
+            }
+        }
+
+        let mut sort_keys = vec![];
+        let content = s
+            .call_on_name("sortKeys", |view: &mut TextArea| {
+                view.get_content().to_string()
+            })
+            .unwrap();
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            let (column, descending) = parse_sort_line(line);
+            if !columns.contains(&column) {
+                return s.add_layer(Dialog::info(format!(
+                    "\"{}\" is not a selected output column.",
+                    column
+                )));
+            }
+            sort_keys.push((column, descending));
+        }
+
+        let mut options = options.clone();
+        options.set_columns(columns, is_checked(s, "dropCategoryColumns"));
+        options.set_header_renames(header_renames);
+        options.set_sort_keys(sort_keys);
+        deduplicate_display(s, options, headers.clone())
+    })
+    .title("Columns");
+
+    columns_dialog.set_focus(DialogFocus::Button(4));
+
+    s.pop_layer();
+    s.add_layer(columns_dialog);
+}
+
+/// Pairs as editable `left = right` lines, like header renames and derived columns.
+fn assignment_lines(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(left, right)| format!("{} = {}", left, right))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Sort keys as editable lines, descending columns end with ` desc`.
+fn sort_lines(sort_keys: &[(String, bool)]) -> String {
+    sort_keys
+        .iter()
+        .map(|(column, descending)| match descending {
+            true => format!("{} desc", column),
+            false => column.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Column and descending of a sort line, ` asc` or ` desc` at the end set the direction.
+fn parse_sort_line(line: &str) -> (String, bool) {
+    let line = line.trim();
+    match line.rsplit_once(' ') {
+        Some((column, direction)) if direction.eq_ignore_ascii_case("desc") => {
+            (column.trim().to_string(), true)
+        }
+        Some((column, direction)) if direction.eq_ignore_ascii_case("asc") => {
+            (column.trim().to_string(), false)
+        }
+        _ => (line.to_string(), false),
+    }
+}
+
+fn column_label(column: &str, checked: bool) -> String {
+    format!("[{}] {}", if checked { "x" } else { " " }, column)
+}
+
+fn toggle_column(s: &mut Cursive) {
+    s.call_on_name("columnsView", |view: &mut SelectView<(String, bool)>| {
+        if let Some(idx) = view.selected_id() {
+            let (column, checked) = view.get_item(idx).unwrap().1.clone();
+            view.remove_item(idx);
+            view.insert_item(idx, column_label(&column, !checked), (column, !checked));
+            view.set_selection(idx);
+        }
+    });
+}
+
+/// Moves the selected column up (-1) or down (1).
+fn move_column(s: &mut Cursive, offset: isize) {
+    s.call_on_name("columnsView", |view: &mut SelectView<(String, bool)>| {
+        if let Some(idx) = view.selected_id() {
+            let target = idx as isize + offset;
+            if target < 0 || target as usize >= view.len() {
+                return;
+            }
+            let (label, item) = view
+                .get_item(idx)
+                .map(|(label, item)| (label.to_string(), item.clone()))
+                .unwrap();
+            view.remove_item(idx);
+            view.insert_item(target as usize, label, item);
+            view.set_selection(target as usize);
+        }
+    });
+}
+
+/// Duplicate removal display
+fn deduplicate_display(s: &mut Cursive, options: Options, headers: StringRecord) {
+    let (back_options, back_headers) = (options.clone(), headers.clone());
+    let (scope, keys, keep_last, write_duplicates) = options.get_dedup();
+    let scopes: Vec<&str> = DEDUP_SCOPES.iter().map(|scope| scope.label()).collect();
+
+    let mut dedup_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Remove duplicated rows:").style(Effect::Bold))
+            .child(select_row(
+                "Duplicates",
+                &scopes,
+                scope.label(),
+                "dedupScope",
+            ))
+            .child(checkbox_row(
+                "Keep the last row instead of the first",
+                keep_last,
+                "dedupKeepLast",
+            ))
+            .child(checkbox_row(
+                "Write removed rows to _duplicates.csv",
+                write_duplicates,
+                "writeDuplicates",
+            ))
+            .child(DummyView)
+            .child(TextView::new(
+                "Key columns (one per line, empty to compare complete rows):",
+            ))
+            .child(
+                TextArea::new()
+                    .content(keys.join("\n"))
+                    .with_name("dedupKeys")
+                    .min_height(3),
+            ),
+    )
+    .button("Back", move |s| {
+        select_columns_display(s, back_options.clone(), back_headers.clone())
+    })
+    .button("Next", move |s| {
+        let scope = selected_format(s, "dedupScope");
+        let scope = *DEDUP_SCOPES
+            .iter()
+            .find(|dedup_scope| dedup_scope.label() == scope)
+            .unwrap();
+        let content = s
+            .call_on_name("dedupKeys", |view: &mut TextArea| {
+                view.get_content().to_string()
+            })
+            .unwrap();
+        let keys: Vec<String> = content
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        if let Some(key) = keys
+            .iter()
+            .find(|key| !headers.iter().any(|header| header == key.as_str()))
+        {
+            return s.add_layer(Dialog::info(format!("\"{}\" is not an input column.", key)));
+        }
+
+        let mut options = options.clone();
+        options.set_dedup(
+            scope,
+            keys,
+            is_checked(s, "dedupKeepLast"),
+            is_checked(s, "writeDuplicates"),
+        );
+        masking_display(s, options, headers.clone())
+    })
+    .title("Duplicates");
+
+    dedup_dialog.set_focus(DialogFocus::Button(1));
+
+    s.pop_layer();
+    s.add_layer(dedup_dialog);
+}
+
+/// Masking display
+///
+/// Hides values of sensitive columns in all outputs.
+fn masking_display(s: &mut Cursive, options: Options, headers: StringRecord) {
+    let (back_options, back_headers) = (options.clone(), headers.clone());
+    let (masks, salt) = options.get_masking();
+
+    let mut masking_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Mask columns (optional):").style(Effect::Bold))
+            .child(TextView::new(MASKING_HELP))
+            .child(DummyView)
+            .child(
+                TextArea::new()
+                    .content(masks.join("\n"))
+                    .with_name("masks")
+                    .min_height(3),
+            )
+            .child(DummyView)
+            .child(TextView::new(
+                "Hashes stay the same across runs with the same salt, keep it secret.",
+            ))
+            .child(edit_row("Salt", &salt, "maskSalt")),
+    )
+    .button("Back", move |s| {
+        deduplicate_display(s, back_options.clone(), back_headers.clone())
+    })
+    .button("Next", move |s| {
+        let masks: Vec<String> = s
+            .call_on_name("masks", |view: &mut TextArea| {
+                view.get_content()
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .unwrap();
+        let parsed = match parse_masks(&masks) {
+            Ok(parsed) => parsed,
+            Err(error) => return s.add_layer(Dialog::info(error)),
+        };
+        if let Some((column, _)) = parsed
+            .iter()
+            .find(|(column, _)| !headers.iter().any(|header| header == column))
+        {
+            return s.add_layer(Dialog::info(format!("Unknown column {}.", column)));
+        }
+        let filter = options.get_filter().map(|(field, _)| field);
+        if let Some((column, _)) = parsed.iter().find(|(column, _)| {
+            *column == options.get_selected_category() || Some(column) == filter.as_ref()
+        }) {
+            return s.add_layer(Dialog::info(format!(
+                "\"{}\" names the output folder, files and tables, its values can't be masked.",
+                column
+            )));
+        }
+        let salt = edit_content(s, "maskSalt");
+        if salt.is_empty() && parsed.iter().any(|(_, mask)| *mask == Mask::Hash) {
+            return s.add_layer(Dialog::info(
+                "Hashing needs a salt, otherwise values can be found by hashing guesses.",
+            ));
+        }
+
+        let mut options = options.clone();
+        options.set_masking(masks, salt);
+        reports_display(s, options, headers.clone())
+    })
+    .title("Masking");
+
+    masking_dialog.set_focus(DialogFocus::Button(1));
+
+    s.pop_layer();
+    s.add_layer(masking_dialog);
+}
+
+/// Reports display
+///
+/// Aggregates written next to the category files.
+fn reports_display(s: &mut Cursive, options: Options, headers: StringRecord) {
+    let (back_options, back_headers) = (options.clone(), headers.clone());
+    let output_columns = output_columns(&options, &headers);
+    let (crosstab_column, crosstab_sum) = options.get_crosstab();
+    let crosstab_columns: Vec<&str> = std::iter::once(NO_CROSSTAB)
+        .chain(output_columns.iter().map(String::as_str))
+        .collect();
+    let crosstab_sums: Vec<&str> = std::iter::once(COUNT_ROWS)
+        .chain(output_columns.iter().map(String::as_str))
+        .collect();
+
+    let mut reports_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Statistics (optional):").style(Effect::Bold))
+            .child(TextView::new(
+                "Sum, average, min, max and distinct count per category,",
+            ))
+            .child(TextView::new("written to summary.csv and summary.xlsx."))
+            .child(DummyView)
+            .child(TextView::new("Columns (one per line, numbers or dates):"))
+            .child(
+                TextArea::new()
+                    .content(options.get_statistics_columns().join("\n"))
+                    .with_name("statisticsColumns")
+                    .min_height(3),
+            )
+            .child(DummyView)
+            .child(TextView::new("Crosstab (optional):").style(Effect::Bold))
+            .child(TextView::new(
+                "Categories against the values of a column, written to crosstab.xlsx.",
+            ))
+            .child(select_row(
+                "Column",
+                &crosstab_columns,
+                crosstab_column.as_deref().unwrap_or(NO_CROSSTAB),
+                "crosstabColumn",
+            ))
+            .child(select_row(
+                "Values",
+                &crosstab_sums,
+                crosstab_sum.as_deref().unwrap_or(COUNT_ROWS),
+                "crosstabSum",
+            )),
+    )
+    .button("Back", move |s| {
+        masking_display(s, back_options.clone(), back_headers.clone())
+    })
+    .button("Next", move |s| {
+        let statistics_columns: Vec<String> = s
+            .call_on_name("statisticsColumns", |view: &mut TextArea| {
+                view.get_content()
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .unwrap();
+        let masked: Vec<String> = parse_masks(&options.get_masking().0)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(column, _)| column)
+            .collect();
+        for column in statistics_columns.iter() {
+            if !output_columns.contains(column) {
+                return s.add_layer(Dialog::info(format!(
+                    "\"{}\" is not an output column.",
+                    column
+                )));
+            }
+            if masked.contains(column) {
+                return s.add_layer(Dialog::info(format!(
+                    "\"{}\" is masked, its statistics would reveal values.",
+                    column
+                )));
+            }
+        }
+
+        let crosstab_column =
+            Some(selected_format(s, "crosstabColumn")).filter(|column| column != NO_CROSSTAB);
+        let crosstab_sum =
+            Some(selected_format(s, "crosstabSum")).filter(|column| column != COUNT_ROWS);
+        if crosstab_column.is_some() && options.get_selected_category().is_empty() {
+            return s.add_layer(Dialog::info(
+                "The crosstab needs a category, go back to select one.",
+            ));
+        }
+        if let Some(column) = crosstab_column
+            .iter()
+            .chain(crosstab_sum.iter())
+            .find(|column| masked.contains(column))
+        {
+            return s.add_layer(Dialog::info(format!(
+                "\"{}\" is masked, the crosstab would reveal values.",
+                column
+            )));
+        }
+
+        let mut options = options.clone();
+        options.set_statistics_columns(statistics_columns);
+        options.set_crosstab(
+            crosstab_column.clone(),
+            crosstab_sum.filter(|_| crosstab_column.is_some()),
+        );
+        output_settings_display(s, options, headers.clone())
+    })
+    .title("Reports");
+
+    reports_dialog.set_focus(DialogFocus::Button(1));
+
+    s.pop_layer();
+    s.add_layer(reports_dialog);
+}
+
+/// Selected category, or a note that the output is only split into parts.
+fn category_label(options: &Options) -> String {
+    match options.get_selected_category() {
+        category if category.is_empty() => "none (parts only)".to_string(),
+        category => category,
+    }
+}
+
+/// Input names of the written columns, without dropped category and filter columns.
+fn output_columns(options: &Options, headers: &StringRecord) -> Vec<String> {
+    let (mut columns, drop_category_columns) = options.get_columns();
+    if columns.is_empty() {
+        columns = headers.iter().map(str::to_string).collect();
+    }
+    if drop_category_columns {
+        let filter = options.get_filter().map(|(field, _)| field);
+        columns.retain(|column| {
+            *column != options.get_selected_category() && Some(column) != filter.as_ref()
+        });
+    }
+    columns
+}
+
+/// Output settings display
+fn output_settings_display(s: &mut Cursive, options: Options, headers: StringRecord) {
+    let (back_options, back_headers) = (options.clone(), headers.clone());
+    let (date_format, datetime_format) = options.get_excel_date_formats();
+    let table_styles: Vec<&str> = std::iter::once(NO_TABLE)
+        .chain(labels(&EXCEL_TABLE_STYLES))
+        .collect();
+    let table_style = options
+        .get_excel_table_style_label()
+        .unwrap_or_else(|| NO_TABLE.to_string());
+    let csv_dialect = options.get_csv_dialect();
+    let sqlite_layouts: Vec<&str> = SQLITE_LAYOUTS.iter().map(|layout| layout.label()).collect();
+    let formats = OUTPUT_FORMATS
+        .chunks(4)
+        .fold(LinearLayout::vertical(), |rows, chunk| {
+            rows.child(
+                chunk
+                    .iter()
+                    .fold(LinearLayout::horizontal(), |row, format| {
+                        row.child(checkbox_row(
+                            &format!("{}  ", format.label()),
+                            options.get_formats().contains(format),
+                            format.label(),
+                        ))
+                    }),
+            )
+        });
+    let (parquet_compression, row_group_size) = options.get_parquet_settings();
+    let (folder_template, file_template) = options.get_naming_templates();
+    let existing_policies: Vec<&str> = EXISTING_POLICIES
+        .iter()
+        .map(|policy| policy.label())
+        .collect();
+    let (csv_formula_policy, xlsx_formula_policy) = options.get_formula_policies();
+    let formula_policies: Vec<&str> = FORMULA_POLICIES
+        .iter()
+        .map(|policy| policy.label())
+        .collect();
+    let (archive_mode, archive_level) = options.get_archive();
+    let archive_modes: Vec<&str> = ARCHIVE_MODES.iter().map(|mode| mode.label()).collect();
+    let (chunk_mode, chunk_size) = options.get_chunking();
+    let chunk_modes: Vec<&str> = CHUNK_MODES.iter().map(|mode| mode.label()).collect();
+
+    let mut settings_dialog = Dialog::around(
+        LinearLayout::vertical()
+            .child(DummyView)
+            .child(TextView::new("Formats:").style(Effect::Bold))
+            .child(formats)
+            .child(DummyView)
+            .child(TextView::new("Excel:").style(Effect::Bold))
+            .child(checkbox_row(
+                "Infer column types",
+                options.get_infer_types(),
+                "inferTypes",
+            ))
+            .child(DummyView)
+            .child(TextView::new(
+                "Date patterns (one per line, \"Column = pattern\" for one column):",
+            ))
+            .child(
+                TextArea::new()
+                    .content(options.get_date_pattern_lines().join("\n"))
+                    .with_name("datePatterns")
+                    .min_height(4),
+            )
+            .child(DummyView)
+            .child(select_row(
+                "Date format:",
+                &EXCEL_DATE_FORMATS,
+                &date_format,
+                "dateFormat",
+            ))
+            .child(select_row(
+                "Datetime format:",
+                &EXCEL_DATETIME_FORMATS,
+                &datetime_format,
+                "datetimeFormat",
+            ))
+            .child(select_row(
+                "Table style:",
+                &table_styles,
+                &table_style,
+                "tableStyle",
+            ))
+            .child(select_row(
+                "Formulas:",
+                &formula_policies,
+                xlsx_formula_policy.label(),
+                "xlsxFormulaPolicy",
+            ))
+            .child(DummyView)
+            .child(TextView::new("CSV:").style(Effect::Bold))
+            .child(select_row(
+                "Delimiter:",
+                &labels(&CSV_DELIMITERS),
+                &csv_dialect.delimiter,
+                "csvDelimiter",
+            ))
+            .child(select_row(
+                "Quoting:",
+                &labels(&CSV_QUOTE_STYLES),
+                &csv_dialect.quote_style,
+                "csvQuoteStyle",
+            ))
+            .child(select_row(
+                "Line endings:",
+                &labels(&CSV_LINE_ENDINGS),
+                &csv_dialect.line_ending,
+                "csvLineEnding",
+            ))
+            .child(checkbox_row(
+                "UTF-8 BOM (Excel on Windows)",
+                csv_dialect.bom,
+                "csvBom",
+            ))
+            .child(select_row(
+                "Formulas:",
+                &formula_policies,
+                csv_formula_policy.label(),
+                "csvFormulaPolicy",
+            ))
+            .child(DummyView)
+            .child(TextView::new("SQLite:").style(Effect::Bold))
+            .child(select_row(
+                "Layout:",
+                &sqlite_layouts,
+                options.get_sqlite_layout().label(),
+                "sqliteLayout",
+            ))
+            .child(DummyView)
+            .child(TextView::new("Parquet:").style(Effect::Bold))
+            .child(select_row(
+                "Compression:",
+                &PARQUET_COMPRESSIONS,
+                &parquet_compression,
+                "parquetCompression",
+            ))
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Row group size:").fixed_width(18))
+                    .child(
+                        EditView::new()
+                            .content(row_group_size.to_string())
+                            .with_name("rowGroupSize")
+                            .fixed_width(12),
+                    ),
+            )
+            .child(DummyView)
+            .child(TextView::new("Archive:").style(Effect::Bold))
+            .child(select_row(
+                "Mode:",
+                &archive_modes,
+                archive_mode.label(),
+                "archiveMode",
+            ))
+            .child(
+                LinearLayout::horizontal()
+                    .child(TextView::new("Level (0-9):").fixed_width(18))
+                    .child(
+                        EditView::new()
+                            .content(archive_level.to_string())
+                            .with_name("archiveLevel")
+                            .fixed_width(12),
+                    ),
+            )
+            .child(DummyView)
+            .child(TextView::new("Parts:").style(Effect::Bold))
+            .child(TextView::new(
+                "Header repeated in every part, sizes like 50000, 500 KB or 10 MB.",
+            ))
+            .child(select_row(
+                "Split into:",
+                &chunk_modes,
+                chunk_mode.label(),
+                "chunkMode",
+            ))
+            .child(edit_row("Part size:", &chunk_size.to_string(), "chunkSize"))
+            .child(DummyView)
+            .child(TextView::new("Names:").style(Effect::Bold))
+            .child(TextView::new(
+                "Placeholders: {category}, {filter}, {input_stem}, {date:%Y-%m-%d}, {rowcount}, {index} (files only)",
+            ))
+            .child(edit_row("Folder:", &folder_template, "folderTemplate"))
+            .child(edit_row("Files:", &file_template, "fileTemplate"))
+            .child(select_row(
+                "Existing folder:",
+                &existing_policies,
+                options.get_existing_policy().label(),
+                "existingPolicy",
+            ))
+            .child(checkbox_row(
+                "Write manifest.json with checksums",
+                options.get_manifest(),
+                "manifest",
+            ))
+            .scrollable(),
+    )
+    .button("Back", move |s| {
+        reports_display(s, back_options.clone(), back_headers.clone())
+    })
+    .button("Next", move |s| {
+        let formats: Vec<_> = OUTPUT_FORMATS
+            .iter()
+            .copied()
+            .filter(|format| is_checked(s, format.label()))
+            .collect();
+        if formats.is_empty() {
+            return s.add_layer(Dialog::info("Select at least one output format."));
+        }
+
+        let row_group_size = match s
+            .call_on_name("rowGroupSize", |view: &mut EditView| view.get_content())
+            .unwrap()
+            .trim()
+            .parse::<usize>()
+        {
+            Ok(row_group_size) if row_group_size > 0 => row_group_size,
+            _ => return s.add_layer(Dialog::info("Row group size must be a positive number.")),
+        };
+
+        let archive_level = match s
+            .call_on_name("archiveLevel", |view: &mut EditView| view.get_content())
+            .unwrap()
+            .trim()
+            .parse::<i64>()
+        {
+            Ok(archive_level) if (0..=9).contains(&archive_level) => archive_level,
+            _ => return s.add_layer(Dialog::info("Archive level must be between 0 and 9.")),
+        };
+
+        let chunk_mode = selected_format(s, "chunkMode");
+        let chunk_mode = *CHUNK_MODES
+            .iter()
+            .find(|mode| mode.label() == chunk_mode)
+            .unwrap();
+        if chunk_mode == ChunkMode::Off && options.get_selected_category().is_empty() {
+            return s.add_layer(Dialog::info(
+                "Without a category the output has to be split into parts.",
+            ));
+        }
+        let chunk_size = edit_content(s, "chunkSize");
+        let chunk_size = match chunk_mode {
+            ChunkMode::Bytes => parse_size(&chunk_size),
+            _ => chunk_size.parse::<usize>().ok().filter(|size| *size > 0),
+        };
+        let chunk_size = match chunk_size {
+            Some(chunk_size) => chunk_size,
+            None => return s.add_layer(Dialog::info("Part size must be a positive size.")),
+        };
+
+        let folder_template = edit_content(s, "folderTemplate");
+        let file_template = edit_content(s, "fileTemplate");
+        if let Err(error) = validate(&folder_template, false)
+            .and_then(|_| validate(&file_template, true))
+        {
+            return s.add_layer(Dialog::info(error));
+        }
+
+        let mut options = options.clone();
+        options.set_naming_templates(folder_template, file_template);
+        options.set_formats(formats);
+        options.set_chunking(chunk_mode, chunk_size);
+        options.set_parquet_settings(selected_format(s, "parquetCompression"), row_group_size);
+        options.set_infer_types(is_checked(s, "inferTypes"));
+        options.set_date_patterns(
+            s.call_on_name("datePatterns", |view: &mut TextArea| {
+                view.get_content()
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap(),
+        );
+        options.set_excel_date_formats(
+            selected_format(s, "dateFormat"),
+            selected_format(s, "datetimeFormat"),
+        );
+        let table_style = selected_format(s, "tableStyle");
+        options.set_excel_table_style(match table_style.as_str() {
+            NO_TABLE => None,
+            _ => Some(table_style),
+        });
+        options.set_csv_dialect(CsvDialect {
+            delimiter: selected_format(s, "csvDelimiter"),
+            quote_style: selected_format(s, "csvQuoteStyle"),
+            line_ending: selected_format(s, "csvLineEnding"),
+            bom: is_checked(s, "csvBom"),
+        });
+        options.set_formula_policies(
+            selected_formula_policy(s, "csvFormulaPolicy"),
+            selected_formula_policy(s, "xlsxFormulaPolicy"),
+        );
+        let sqlite_layout = selected_format(s, "sqliteLayout");
+        options.set_sqlite_layout(
+            *SQLITE_LAYOUTS
+                .iter()
+                .find(|layout| layout.label() == sqlite_layout)
+                .unwrap(),
+        );
+        let archive_mode = selected_format(s, "archiveMode");
+        options.set_archive(
+            *ARCHIVE_MODES
+                .iter()
+                .find(|mode| mode.label() == archive_mode)
+                .unwrap(),
+            archive_level,
+        );
+        let existing_policy = selected_format(s, "existingPolicy");
+        let existing_policy = *EXISTING_POLICIES
+            .iter()
+            .find(|policy| policy.label() == existing_policy)
+            .unwrap();
+        if existing_policy == ExistingPolicy::Append && !options.can_append() {
+            return s.add_layer(Dialog::info(
+                "Only CSV, JSON and NDJSON files can be appended, without parts or ZIP archives replacing them.",
+            ));
+        }
+        options.set_existing_policy(existing_policy);
+        options.set_manifest(is_checked(s, "manifest"));
+        overview_display(s, options, headers.clone())
+    })
+    .title("Output settings");
+
+    settings_dialog.set_focus(DialogFocus::Button(1));
+
+    s.pop_layer();
+    s.add_layer(settings_dialog);
+}
+
+/// Labels of a list of `(label, value)` choices.
+fn labels<T>(entries: &[(&'static str, T)]) -> Vec<&'static str> {
+    entries.iter().map(|(label, _)| *label).collect()
+}
+
+/// Setting with a label and a popup select.
+fn select_row(label: &str, formats: &[&str], current: &str, name: &str) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(TextView::new(label).fixed_width(18))
+        .child(format_select(formats, current).with_name(name))
+}
+
+/// Setting with a checkbox followed by its label.
+fn checkbox_row(label: &str, checked: bool, name: &str) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(Checkbox::new().with_checked(checked).with_name(name))
+        .child(TextView::new(format!(" {}", label)))
+}
+
+/// Setting with a label and a single line text input.
+fn edit_row(label: &str, content: &str, name: &str) -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(TextView::new(label).fixed_width(18))
+        .child(
+            EditView::new()
+                .content(content)
+                .with_name(name)
+                .min_width(30),
+        )
+}
+
+fn selected_formula_policy(s: &mut Cursive, name: &str) -> FormulaPolicy {
+    let label = selected_format(s, name);
+    *FORMULA_POLICIES
+        .iter()
+        .find(|policy| policy.label() == label)
+        .unwrap()
+}
+
+fn edit_content(s: &mut Cursive, name: &str) -> String {
+    s.call_on_name(name, |view: &mut EditView| view.get_content())
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+fn is_checked(s: &mut Cursive, name: &str) -> bool {
+    s.call_on_name(name, |view: &mut Checkbox| view.is_checked())
+        .unwrap()
+}
+
+/// Popup select with the given formats, starting at the current one.
+fn format_select(formats: &[&str], current: &str) -> SelectView {
+    let mut select = SelectView::new().popup();
+    formats
+        .iter()
+        .for_each(|format| select.add_item(format.to_string(), format.to_string()));
+    let idx = formats
+        .iter()
+        .position(|format| *format == current)
+        .unwrap_or(0);
+    select.set_selection(idx);
+    select
+}
+
+fn selected_format(s: &mut Cursive, name: &str) -> String {
+    s.call_on_name(name, |view: &mut SelectView| {
+        view.selection().unwrap().to_string()
+    })
+    .unwrap()
+}
+
 /// Overview display
 fn overview_display(siv: &mut Cursive, options: Options, headers: StringRecord) {
     let mut overview = LinearLayout::vertical()
         .child(DummyView)
         .child(TextView::new(format!(
             "Category: {}",
-            options.get_selected_category()
+            category_label(&options)
         )));
 
     if let Some((filter_field, filter_value)) = options.get_filter() {
@@ -234,12 +1528,194 @@ fn overview_display(siv: &mut Cursive, options: Options, headers: StringRecord)
         )))
     }
 
+    overview = overview.child(TextView::new(format!(
+        "Infer column types: {}",
+        if options.get_infer_types() {
+            "yes"
+        } else {
+            "no"
+        }
+    )));
+
+    let (date_format, datetime_format) = options.get_excel_date_formats();
+    overview = overview.child(TextView::new(format!(
+        "Excel date formats: '{}' and '{}'",
+        date_format, datetime_format
+    )));
+
+    let cleaning_rules = options.get_cleaning_rules();
+    if !cleaning_rules.is_empty() {
+        overview = overview.child(TextView::new(format!(
+            "Cleaning: {}",
+            cleaning_rules.join("; ")
+        )));
+    }
+    if let (Some(file), input_key, reference_key, join) = options.get_lookup() {
+        overview = overview.child(TextView::new(format!(
+            "Lookup: {} by {} = {}, {}",
+            file.file_name().unwrap_or_default().to_string_lossy(),
+            input_key,
+            reference_key,
+            join.label().to_lowercase()
+        )));
+    }
+    let derived_columns = options.get_derived_columns();
+    if !derived_columns.is_empty() {
+        let derived: Vec<String> = derived_columns
+            .iter()
+            .map(|(name, expression)| format!("{} = {}", name, expression))
+            .collect();
+        overview = overview.child(TextView::new(format!(
+            "Derived columns: {}",
+            derived.join(", ")
+        )));
+    }
+    let (columns, drop_category_columns) = options.get_columns();
+    if columns.len() != headers.len() || columns.iter().zip(headers.iter()).any(|(a, b)| a != b) {
+        overview = overview.child(TextView::new(format!("Columns: {}", columns.join(", "))));
+    }
+    if drop_category_columns {
+        overview = overview.child(TextView::new("Category and filter columns are dropped"));
+    }
+    let header_renames = options.get_header_renames();
+    if !header_renames.is_empty() {
+        let renames: Vec<String> = header_renames
+            .iter()
+            .map(|(from, to)| format!("{} -> {}", from, to))
+            .collect();
+        overview = overview.child(TextView::new(format!(
+            "Renamed headers: {}",
+            renames.join(", ")
+        )));
+    }
+    let (scope, keys, keep_last, write_duplicates) = options.get_dedup();
+    if scope != DedupScope::Off {
+        overview = overview.child(TextView::new(format!(
+            "Duplicates: removed {} by {}, keeping the {} row{}",
+            scope.label().to_lowercase(),
+            match keys.is_empty() {
+                true => "complete rows".to_string(),
+                false => keys.join(", "),
+            },
+            if keep_last { "last" } else { "first" },
+            if write_duplicates {
+                ", written to _duplicates.csv"
+            } else {
+                ""
+            }
+        )));
+    }
+    let (masks, _) = options.get_masking();
+    if !masks.is_empty() {
+        overview = overview.child(TextView::new(format!("Masks: {}", masks.join("; "))));
+    }
+    let statistics_columns = options.get_statistics_columns();
+    if !statistics_columns.is_empty() {
+        overview = overview.child(TextView::new(format!(
+            "Statistics: {}",
+            statistics_columns.join(", ")
+        )));
+    }
+    if let (Some(column), sum) = options.get_crosstab() {
+        overview = overview.child(TextView::new(format!(
+            "Crosstab: {} by {}, {}",
+            options.get_selected_category(),
+            column,
+            match sum {
+                Some(sum) => format!("sum of {}", sum),
+                None => "row counts".to_string(),
+            }
+        )));
+    }
+    match options.get_chunking() {
+        (ChunkMode::Off, _) => {}
+        (ChunkMode::Rows, size) => {
+            overview = overview.child(TextView::new(format!("Parts: at most {} rows", size)))
+        }
+        (ChunkMode::Bytes, size) => {
+            overview = overview.child(TextView::new(format!("Parts: at most {} bytes", size)))
+        }
+    }
+    let sort_keys = options.get_sort_keys();
+    if !sort_keys.is_empty() {
+        overview = overview.child(TextView::new(format!(
+            "Sorted by: {}",
+            sort_lines(&sort_keys).replace('\n', ", ")
+        )));
+    }
+
+    let formats: Vec<&str> = options
+        .get_formats()
+        .iter()
+        .map(|format| format.label())
+        .collect();
+    overview = overview.child(TextView::new(format!("Formats: {}", formats.join(", "))));
+
+    if options.get_formats().contains(&OutputFormat::Parquet) {
+        let (compression, row_group_size) = options.get_parquet_settings();
+        overview = overview.child(TextView::new(format!(
+            "Parquet: {}, row groups of {} rows",
+            compression, row_group_size
+        )));
+    }
+
+    if options.get_formats().contains(&OutputFormat::Sqlite) {
+        overview = overview.child(TextView::new(format!(
+            "SQLite layout: {}",
+            options.get_sqlite_layout().label()
+        )));
+    }
+
+    let csv_dialect = options.get_csv_dialect();
+    overview = overview.child(TextView::new(format!(
+        "CSV: {}, quoting {}, {}{}",
+        csv_dialect.delimiter,
+        csv_dialect.quote_style.to_lowercase(),
+        csv_dialect.line_ending,
+        if csv_dialect.bom { ", BOM" } else { "" }
+    )));
+
+    if let Some(table_style) = options.get_excel_table_style_label() {
+        overview = overview.child(TextView::new(format!("Excel table style: {}", table_style)))
+    }
+
+    let (csv_formula_policy, xlsx_formula_policy) = options.get_formula_policies();
+    overview = overview.child(TextView::new(format!(
+        "Formulas: CSV {}, Excel {}",
+        csv_formula_policy.label().to_lowercase(),
+        xlsx_formula_policy.label().to_lowercase()
+    )));
+
+    let (folder_template, file_template) = options.get_naming_templates();
+    overview = overview.child(TextView::new(format!(
+        "Names: folder '{}', files '{}'",
+        folder_template, file_template
+    )));
+
+    overview = overview.child(TextView::new(format!(
+        "Existing folder: {}",
+        options.get_existing_policy().label()
+    )));
+
+    if options.get_manifest() {
+        overview = overview.child(TextView::new("Manifest: manifest.json with SHA-256"))
+    }
+
+    let (archive_mode, archive_level) = options.get_archive();
+    if archive_mode != ArchiveMode::None {
+        overview = overview.child(TextView::new(format!(
+            "Archive: {}, level {}",
+            archive_mode.label(),
+            archive_level
+        )))
+    }
+
     let (back_options, back_headers) = (options.clone(), headers.clone());
 
     let mut dialog = Dialog::around(overview)
         .title("Overview")
         .button("Back", move |s| {
-            select_filter_display(s, back_options.clone(), back_headers.clone())
+            output_settings_display(s, back_options.clone(), back_headers.clone())
         })
         .button("Abort", |s| s.quit())
         .button("Execute", move |s| {
@@ -254,13 +1730,10 @@ fn overview_display(siv: &mut Cursive, options: Options, headers: StringRecord)
 }
 
 fn execute(s: &mut Cursive, options: Options, headers: StringRecord) {
-    progress_display(s);
+    let transformer = Transformer::new(s.cb_sink().clone(), options, headers);
+    progress_display(s, transformer.get_cancel_flag());
 
-    let transformer = Arc::new(Mutex::new(Transformer::new(
-        s.cb_sink().clone(),
-        options,
-        headers,
-    )));
+    let transformer = Arc::new(Mutex::new(transformer));
 
     std::thread::spawn(move || {
         let mut transformer = transformer.lock().unwrap();
@@ -292,36 +1765,112 @@ fn error_display(s: &mut Cursive, error: String) {
 }
 
 /// Will be displayed during execution. Content will be updated within [`Transformer`].
-fn progress_display(s: &mut Cursive) {
+///
+/// Cancel stops the run with its next update, staged files are removed.
+fn progress_display(s: &mut Cursive, cancel: Arc<AtomicBool>) {
     s.pop_layer();
     s.add_layer(
         Dialog::new()
             .title("Execution")
-            .content(TextView::new("").with_name("running").min_width(15)),
+            .content(TextView::new("").with_name("running").min_width(15))
+            .button("Cancel", move |_| cancel.store(true, Ordering::Relaxed)),
     );
 }
 
 /// Finished display
-fn finished_display(
-    s: &mut Cursive,
-    (cat_total, csv_rl, csv_wl, excel_wl): (i32, i32, i32, i32),
-    file_paths: Option<(String, String)>,
-) {
+fn finished_display(s: &mut Cursive, summary: Summary, file_paths: Option<(String, String)>) {
+    let mut stats = LinearLayout::vertical()
+        .child(TextView::new("Finished."))
+        .child(DummyView)
+        .child(TextView::new(format!(
+            "Categories:          {}",
+            summary.cat_total
+        )))
+        .child(TextView::new(format!(
+            "CSV lines read:      {}",
+            summary.csv_rl
+        )));
+    for (format, lines) in summary.written {
+        stats = stats.child(TextView::new(format!(
+            "{:<21}{}",
+            format!("{} lines written:", format.label()),
+            lines
+        )));
+    }
+
+    if let Some(duplicates) = summary.duplicates {
+        stats = stats.child(TextView::new(format!(
+            "Duplicates removed:  {}",
+            duplicates
+        )));
+    }
+
+    if let Some(unmatched_keys) = summary.unmatched_keys.filter(|keys| !keys.is_empty()) {
+        stats = stats.child(DummyView).child(TextView::new(format!(
+            "Lookup keys without match: {} ({} rows)",
+            unmatched_keys.len(),
+            unmatched_keys.iter().map(|(_, rows)| rows).sum::<usize>()
+        )));
+        for (key, rows) in unmatched_keys.iter() {
+            stats = stats.child(TextView::new(format!("  '{}': {} rows", key, rows)));
+        }
+    }
+
+    if !summary.formula_cells.is_empty() {
+        stats = stats.child(DummyView).child(TextView::new(format!(
+            "Values neutralized as formulas: {}",
+            summary.formula_cells.len()
+        )));
+        for cell in summary.formula_cells.iter() {
+            stats = stats.child(TextView::new(format!(
+                "  {}, row {}, {}",
+                cell.file, cell.row, cell.column
+            )));
+        }
+    }
+
+    if !summary.statistics.is_empty() {
+        stats = stats.child(DummyView).child(TextView::new("Statistics:"));
+        for (category, columns) in summary.statistics.iter() {
+            stats = stats.child(TextView::new(format!("  {}", category)));
+            for (column, column_stats) in columns {
+                stats = stats.child(TextView::new(format!(
+                    "    {}: {} values, sum {}, average {}, min {}, max {}, {} distinct",
+                    column,
+                    column_stats.count,
+                    column_stats.sum().map_or("-".to_string(), format_number),
+                    column_stats
+                        .average()
+                        .map_or("-".to_string(), format_number),
+                    column_stats.min().map_or("-".to_string(), format_value),
+                    column_stats.max().map_or("-".to_string(), format_value),
+                    column_stats.distinct()
+                )));
+            }
+        }
+    }
+
+    let renamed: Vec<_> = summary
+        .file_names
+        .iter()
+        .filter(|(category, file_name)| category != file_name)
+        .collect();
+    if !renamed.is_empty() {
+        stats = stats
+            .child(DummyView)
+            .child(TextView::new("File names of categories:"));
+        for (category, file_name) in renamed {
+            stats = stats.child(TextView::new(format!("  '{}' -> {}", category, file_name)));
+        }
+    }
+
     s.pop_layer();
     s.add_layer(
-        Dialog::around(
-            LinearLayout::vertical()
-                .child(TextView::new("Finished."))
-                .child(DummyView)
-                .child(TextView::new(format!("Categories:          {}", cat_total)))
-                .child(TextView::new(format!("CSV lines read:      {}", csv_rl)))
-                .child(TextView::new(format!("CSV lines written:   {}", csv_wl)))
-                .child(TextView::new(format!("Excel lines written: {}", excel_wl))),
-        )
-        .title("Success")
-        .button("New", move |s| {
-            select_file_and_directory_display(s, file_paths.clone())
-        })
-        .button("Close", |s| s.quit()),
+        Dialog::around(stats.scrollable())
+            .title("Success")
+            .button("New", move |s| {
+                select_file_and_directory_display(s, file_paths.clone())
+            })
+            .button("Close", |s| s.quit()),
     )
 }
@@ -0,0 +1,121 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Local};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+
+use crate::transform::OutputFile;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Writes `manifest.json` describing the input, the options and every generated file.
+///
+/// Row counts are the rows written by this run, appended files may contain more.
+pub fn write_manifest(
+    folder: &Path,
+    input: &Path,
+    options: JsonValue,
+    files: &[OutputFile],
+    created: DateTime<Local>,
+) -> Result<(), Box<dyn Error>> {
+    let (input_bytes, input_sha256) = checksum(input)?;
+    let mut entries = vec![];
+    for file in files {
+        let (bytes, sha256) = checksum(&file.path)?;
+        entries.push(json!({
+            "file": file.path.file_name().unwrap().to_string_lossy(),
+            "category": file.category,
+            "rows": file.rows,
+            "bytes": bytes,
+            "sha256": sha256,
+        }));
+    }
+    let manifest = json!({
+        "tool": {
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "created": created.to_rfc3339(),
+        "input": {
+            "path": input.to_string_lossy(),
+            "bytes": input_bytes,
+            "sha256": input_sha256,
+        },
+        "options": options,
+        "files": entries,
+    });
+
+    let mut wtr = BufWriter::new(File::create(folder.join(MANIFEST_FILE))?);
+    serde_json::to_writer_pretty(&mut wtr, &manifest)?;
+    wtr.write_all(b"\n")?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Size and hex encoded SHA-256 of a file.
+fn checksum(path: &Path) -> Result<(u64, String), Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    let bytes = io::copy(&mut File::open(path)?, &mut hasher)?;
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok((bytes, sha256))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn lists_files_with_checksums() {
+        let folder =
+            std::env::temp_dir().join(format!("csv-helper-manifest-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let input = folder.join("input.csv");
+        fs::write(&input, "abc").unwrap();
+        let output = folder.join("Nord.csv");
+        fs::write(&output, "").unwrap();
+        let files = vec![OutputFile {
+            path: output,
+            category: Some("Nord".to_string()),
+            rows: 0,
+        }];
+
+        write_manifest(
+            &folder,
+            &input,
+            json!({"infer_types": true}),
+            &files,
+            Local::now(),
+        )
+        .unwrap();
+        let manifest: JsonValue =
+            serde_json::from_str(&fs::read_to_string(folder.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest["tool"]["name"], "csv-helper");
+        assert_eq!(manifest["input"]["bytes"], 3);
+        assert_eq!(
+            manifest["input"]["sha256"],
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(manifest["options"]["infer_types"], true);
+        assert_eq!(
+            manifest["files"],
+            json!([{
+                "file": "Nord.csv",
+                "category": "Nord",
+                "rows": 0,
+                "bytes": 0,
+                "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            }])
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
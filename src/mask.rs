@@ -0,0 +1,279 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime};
+use csv::StringRecord;
+use sha2::{Digest, Sha256};
+
+use crate::naming::{is_valid_date_pattern, try_format};
+
+/// Replacement of redacted values.
+const REDACTED: &str = "***";
+
+/// Short reference of the mask syntax for the wizard.
+pub const MASKING_HELP: &str = "Column = mask, one per line
+Masks: hash (salted SHA-256), redact, keep_last(4), month (date truncated to month)";
+
+/// How the values of a column are hidden in the output. Empty values stay empty.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mask {
+    /// Hex encoded SHA-256 of salt and value, equal values get equal hashes.
+    ///
+    /// The salt is prefixed by its length, so salt `ab` with value `c` and salt `a` with
+    /// value `bc` don't hash the same input.
+    Hash,
+    Redact,
+    /// Every character except the last ones becomes `*`, values which aren't longer than
+    /// the kept characters are masked completely.
+    KeepLast(usize),
+    /// Dates become the first day of their month, in the pattern they were read with.
+    Month,
+}
+
+impl Mask {
+    fn apply(self, value: &str, salt: &str, date_patterns: &[String]) -> String {
+        if value.trim().is_empty() {
+            return value.to_string();
+        }
+        match self {
+            Mask::Hash => Sha256::new()
+                .chain_update((salt.len() as u64).to_le_bytes())
+                .chain_update(salt)
+                .chain_update(value)
+                .finalize()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            Mask::Redact => REDACTED.to_string(),
+            Mask::KeepLast(count) => {
+                let length = value.chars().count();
+                value
+                    .chars()
+                    .enumerate()
+                    .map(|(idx, c)| match idx + count < length || length <= count {
+                        true => '*',
+                        false => c,
+                    })
+                    .collect()
+            }
+            Mask::Month => {
+                truncate_to_month(value, date_patterns).unwrap_or_else(|| REDACTED.to_string())
+            }
+        }
+    }
+}
+
+/// First day of the month at midnight, formatted with the pattern which parsed the value.
+///
+/// `None` if no pattern matches, the value is redacted then instead of leaking.
+fn truncate_to_month(value: &str, date_patterns: &[String]) -> Option<String> {
+    for pattern in date_patterns
+        .iter()
+        .filter(|pattern| is_valid_date_pattern(pattern))
+    {
+        // before the naive datetime, which accepts offsets but can't format them
+        if let Ok(datetime) = DateTime::parse_from_str(value, pattern) {
+            let month = datetime
+                .date_naive()
+                .with_day(1)?
+                .and_hms_opt(0, 0, 0)?
+                .and_local_timezone(datetime.timezone())
+                .single()?;
+            return try_format(month.format(pattern));
+        }
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, pattern) {
+            let month = datetime.date().with_day(1)?.and_hms_opt(0, 0, 0)?;
+            return try_format(month.format(pattern));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(value, pattern) {
+            return try_format(date.with_day(1)?.format(pattern));
+        }
+    }
+    None
+}
+
+/// Parses `Column = mask` lines.
+pub fn parse_masks(lines: &[String]) -> Result<Vec<(String, Mask)>, String> {
+    let mut masks = vec![];
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        let (column, mask) = line
+            .split_once('=')
+            .ok_or_else(|| format!("\"{}\" is not \"Column = mask\".", line))?;
+        let mask = mask.trim().to_lowercase();
+        let mask = match mask.as_str() {
+            "hash" => Mask::Hash,
+            "redact" => Mask::Redact,
+            "month" => Mask::Month,
+            _ => match mask
+                .strip_prefix("keep_last(")
+                .and_then(|count| count.strip_suffix(')'))
+                .and_then(|count| count.trim().parse().ok())
+            {
+                Some(count) => Mask::KeepLast(count),
+                None => return Err(format!("Unknown mask \"{}\".", mask)),
+            },
+        };
+        masks.push((column.trim().to_string(), mask));
+    }
+    Ok(masks)
+}
+
+/// Masks of the columns of one header, with their date patterns.
+pub struct Masker {
+    columns: Vec<Option<(Mask, Vec<String>)>>,
+    salt: String,
+}
+
+impl Masker {
+    /// Masks of columns missing in `headers` are ignored, these columns aren't written.
+    pub fn new(
+        lines: &[String],
+        salt: &str,
+        headers: &StringRecord,
+        date_patterns: impl Fn(&str) -> Vec<String>,
+    ) -> Result<Masker, String> {
+        let masks = parse_masks(lines)?;
+        let columns = headers
+            .iter()
+            .map(|header| {
+                masks
+                    .iter()
+                    .find(|(column, _)| column == header)
+                    .map(|(_, mask)| (*mask, date_patterns(header)))
+            })
+            .collect();
+        Ok(Masker {
+            columns,
+            salt: salt.to_string(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(Option::is_none)
+    }
+
+    pub fn mask(&self, record: &StringRecord) -> StringRecord {
+        record
+            .iter()
+            .zip(self.columns.iter())
+            .map(|(field, mask)| match mask {
+                Some((mask, date_patterns)) => mask.apply(field, &self.salt, date_patterns),
+                None => field.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_masks() {
+        let lines: Vec<String> = vec![
+            "IBAN = keep_last( 4 )".to_string(),
+            " ".to_string(),
+            "Name=HASH".to_string(),
+        ];
+        assert_eq!(
+            parse_masks(&lines).unwrap(),
+            vec![
+                ("IBAN".to_string(), Mask::KeepLast(4)),
+                ("Name".to_string(), Mask::Hash)
+            ]
+        );
+        assert_eq!(
+            parse_masks(&["Name hash".to_string()]).unwrap_err(),
+            "\"Name hash\" is not \"Column = mask\"."
+        );
+        assert_eq!(
+            parse_masks(&["Name = keep_last(x)".to_string()]).unwrap_err(),
+            "Unknown mask \"keep_last(x)\"."
+        );
+    }
+
+    #[test]
+    fn masks_configured_columns() {
+        let headers = StringRecord::from(vec!["Name", "IBAN", "Born", "City", "Note"]);
+        let lines: Vec<String> = vec![
+            "Name = hash".to_string(),
+            "IBAN = keep_last(4)".to_string(),
+            "Born = month".to_string(),
+            "Note = redact".to_string(),
+            "Missing = redact".to_string(),
+        ];
+        let masker = Masker::new(&lines, "salt", &headers, |_| patterns(&["%Y-%m-%d"])).unwrap();
+        assert!(!masker.is_empty());
+
+        let masked = masker.mask(&StringRecord::from(vec![
+            "Anna",
+            "DE1234567890",
+            "1990-07-15",
+            "Köln",
+            "secret",
+        ]));
+        assert_eq!(masked[0].len(), 64);
+        assert!(masked[0].chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(
+            &masked.iter().skip(1).collect::<Vec<_>>(),
+            &["********7890", "1990-07-01", "Köln", REDACTED]
+        );
+        let empty = masker.mask(&StringRecord::from(vec!["", " ", "", "", ""]));
+        assert_eq!(empty, StringRecord::from(vec!["", " ", "", "", ""]));
+    }
+
+    #[test]
+    fn equal_values_equal_hashes() {
+        let hash = |value: &str, salt: &str| Mask::Hash.apply(value, salt, &[]);
+        assert_eq!(hash("Anna", "salt"), hash("Anna", "salt"));
+        assert_ne!(hash("Anna", "salt"), hash("Anna", "pepper"));
+        assert_ne!(hash("Anna", "salt"), hash("Ben", "salt"));
+        assert_ne!(hash("Anna", "salt"), hash("nna", "saltA"));
+    }
+
+    #[test]
+    fn hashes_stable_across_runs() {
+        assert_eq!(
+            Mask::Hash.apply("Anna", "salt", &[]),
+            "3bbcdb8dfa10e786fa474861adae1e21685f7d00f6399559cab056e02bad9258"
+        );
+    }
+
+    #[test]
+    fn short_values_masked_completely() {
+        let keep_last = |value: &str| Mask::KeepLast(4).apply(value, "", &[]);
+        assert_eq!(keep_last("DE1234"), "**1234");
+        assert_eq!(keep_last("12345"), "*2345");
+        assert_eq!(keep_last("1234"), "****");
+        assert_eq!(keep_last("äb"), "**");
+    }
+
+    #[test]
+    fn month_keeps_pattern_and_offset() {
+        assert_eq!(
+            truncate_to_month("2024-03-05T10:00:00+01:00", &patterns(&["%+"])).as_deref(),
+            Some("2024-03-01T00:00:00+01:00")
+        );
+        assert_eq!(
+            truncate_to_month("5.3.2024 10:00:00", &patterns(&["%d.%m.%Y %H:%M:%S"])).as_deref(),
+            Some("01.03.2024 00:00:00")
+        );
+        assert_eq!(
+            truncate_to_month("2024-03-05", &patterns(&["%Y-%m-%d"])).as_deref(),
+            Some("2024-03-01")
+        );
+    }
+
+    #[test]
+    fn unformattable_month_is_redacted() {
+        assert_eq!(
+            Mask::Month.apply("2024-03-05+01:00", "", &patterns(&["%Y-%m-%d%:z"])),
+            REDACTED
+        );
+        assert_eq!(
+            Mask::Month.apply("no date", "", &patterns(&["%Y-%m-%d"])),
+            REDACTED
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt::{self, Write};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+
+/// Default template of the output folder, `{filter}` is empty without filter.
+pub const FOLDER_TEMPLATE: &str = "{category}_{filter}";
+
+/// Default template of the file names, without extension.
+pub const FILE_TEMPLATE: &str = "{category}";
+
+/// Pattern of `{date}` without explicit pattern.
+const DATE_PATTERN: &str = "%Y-%m-%d";
+
+/// Separators left over at the start or end of a name by empty placeholders.
+const SEPARATORS: [char; 3] = ['_', '-', ' '];
+
+/// Values of the placeholders for one name.
+///
+/// For the folder `category` is the selected category column and `rowcount` the number of
+/// lines read, for a file it is the category value and its number of rows. Without a
+/// category both use the input stem.
+pub struct NameContext<'a> {
+    pub category: &'a str,
+    pub filter: Option<&'a (String, String)>,
+    pub input_stem: &'a str,
+    pub rowcount: usize,
+    /// Position of the category in alphabetical order, starting at 1. Only for files.
+    pub index: Option<usize>,
+    pub date: DateTime<Local>,
+}
+
+/// Literal text or placeholder of a template.
+enum Part<'a> {
+    Text(&'a str),
+    Category,
+    Filter,
+    InputStem,
+    Date(&'a str),
+    Rowcount,
+    Index,
+}
+
+/// Splits a template into literal text and placeholders.
+fn parse(template: &str) -> Result<Vec<Part<'_>>, String> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        parts.push(Part::Text(&rest[..start]));
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => return Err(format!("Missing '}}' in \"{}\".", template)),
+        };
+        let name = &rest[start + 1..end];
+        parts.push(match name.split_once(':') {
+            Some(("date", pattern)) => Part::Date(pattern),
+            _ => match name {
+                "category" => Part::Category,
+                "filter" => Part::Filter,
+                "input_stem" => Part::InputStem,
+                "date" => Part::Date(DATE_PATTERN),
+                "rowcount" => Part::Rowcount,
+                "index" => Part::Index,
+                _ => return Err(format!("Unknown placeholder {{{}}}.", name)),
+            },
+        });
+        rest = &rest[end + 1..];
+    }
+    parts.push(Part::Text(rest));
+    Ok(parts)
+}
+
+/// Checks a template before the run.
+///
+/// `{index}` is only known for files, file names need `{category}` or `{index}` to be unique.
+pub fn validate(template: &str, for_files: bool) -> Result<(), String> {
+    let parts = parse(template)?;
+    if for_files
+        && !parts
+            .iter()
+            .any(|part| matches!(part, Part::Category | Part::Index))
+    {
+        return Err("File names need {category} or {index}.".to_string());
+    }
+    for part in parts {
+        match part {
+            Part::Index if !for_files => {
+                return Err("{index} is only available for file names.".to_string())
+            }
+            Part::Date(pattern) if !is_valid_date_pattern(pattern) => {
+                return Err(format!("Invalid date pattern \"{}\".", pattern))
+            }
+            _ => (),
+        }
+    }
+    match render(template, &example_context(for_files)).is_empty() {
+        true => Err(format!("\"{}\" results in an empty name.", template)),
+        false => Ok(()),
+    }
+}
+
+/// Formatting an invalid pattern panics, so it has to be checked first.
+pub fn is_valid_date_pattern(pattern: &str) -> bool {
+    !StrftimeItems::new(pattern).any(|item| item == Item::Error)
+}
+
+/// Formatted date, `None` if the pattern needs a field the value lacks, like the offset of a
+/// naive datetime, where `to_string` would panic.
+pub fn try_format(date: impl fmt::Display) -> Option<String> {
+    let mut text = String::new();
+    write!(text, "{}", date).ok()?;
+    Some(text)
+}
+
+fn example_context(for_files: bool) -> NameContext<'static> {
+    NameContext {
+        category: "category",
+        filter: None,
+        input_stem: "input",
+        rowcount: 1,
+        index: if for_files { Some(1) } else { None },
+        date: Local::now(),
+    }
+}
+
+/// Replaces the placeholders, separators at the start and end are removed.
+///
+/// Invalid templates are rejected by [`validate`] and rendered as they are.
+pub fn render(template: &str, context: &NameContext) -> String {
+    let parts = match parse(template) {
+        Ok(parts) => parts,
+        Err(_) => return template.to_string(),
+    };
+    let name: String = parts
+        .into_iter()
+        .map(|part| match part {
+            Part::Text(text) => text.to_string(),
+            Part::Category => context.category.to_string(),
+            Part::Filter => match context.filter {
+                Some((filter_field, filter_value)) => {
+                    format!("{}_{}", filter_field, filter_value)
+                }
+                None => String::new(),
+            },
+            Part::InputStem => context.input_stem.to_string(),
+            Part::Date(pattern) if is_valid_date_pattern(pattern) => {
+                context.date.format(pattern).to_string()
+            }
+            Part::Date(pattern) => pattern.to_string(),
+            Part::Rowcount => context.rowcount.to_string(),
+            Part::Index => context
+                .index
+                .map(|index| index.to_string())
+                .unwrap_or_default(),
+        })
+        .collect();
+    name.trim_matches(&SEPARATORS[..]).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn render_file(template: &str, filter: Option<&(String, String)>) -> String {
+        render(
+            template,
+            &NameContext {
+                category: "Nord",
+                filter,
+                input_stem: "orders",
+                rowcount: 42,
+                index: Some(3),
+                date: Local.with_ymd_and_hms(2024, 3, 5, 10, 30, 0).unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let filter = ("Flag".to_string(), "true".to_string());
+        assert_eq!(
+            render_file(FOLDER_TEMPLATE, Some(&filter)),
+            "Nord_Flag_true"
+        );
+        assert_eq!(render_file(FILE_TEMPLATE, None), "Nord");
+        assert_eq!(
+            render_file("{input_stem}-{index}-{rowcount}-{date}", None),
+            "orders-3-42-2024-03-05"
+        );
+        assert_eq!(
+            render_file("{date:%Y%m%d_%H%M} {category}", None),
+            "20240305_1030 Nord"
+        );
+    }
+
+    #[test]
+    fn trims_separators_of_empty_placeholders() {
+        assert_eq!(render_file(FOLDER_TEMPLATE, None), "Nord");
+        assert_eq!(render_file("{filter}-{category}_", None), "Nord");
+    }
+
+    #[test]
+    fn validates_templates() {
+        assert_eq!(validate(FOLDER_TEMPLATE, false), Ok(()));
+        assert_eq!(validate("{index}_{date:%Y}", true), Ok(()));
+        assert_eq!(
+            validate("{category", false),
+            Err("Missing '}' in \"{category\".".to_string())
+        );
+        assert_eq!(
+            validate("{nope}", false),
+            Err("Unknown placeholder {nope}.".to_string())
+        );
+        assert_eq!(
+            validate("{input_stem}", true),
+            Err("File names need {category} or {index}.".to_string())
+        );
+        assert_eq!(
+            validate("{index}", false),
+            Err("{index} is only available for file names.".to_string())
+        );
+        assert_eq!(
+            validate("{category}{date:%Q}", true),
+            Err("Invalid date pattern \"%Q\".".to_string())
+        );
+        assert_eq!(
+            validate("{filter}_", false),
+            Err("\"{filter}_\" results in an empty name.".to_string())
+        );
+    }
+
+    #[test]
+    fn formatting_without_offset_fails() {
+        let datetime = chrono::NaiveDate::from_ymd_opt(2024, 3, 5)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        assert_eq!(
+            try_format(datetime.format("%d.%m.%Y")).as_deref(),
+            Some("05.03.2024")
+        );
+        assert_eq!(try_format(datetime.format("%z")), None);
+    }
+}
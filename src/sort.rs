@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+
+use csv::StringRecord;
+
+use crate::infer::{Schema, Value};
+
+/// Sorts records (header excluded) by the given `(column, descending)` keys.
+///
+/// Values are compared by the column types of the schema: numbers by value, dates in time
+/// and text collated (case and accents only decide ties). Empty fields come last in both
+/// directions, equal rows keep their input order.
+///
+/// The records of all categories are already held in memory by `read_csv`, so the sort
+/// works in memory as well.
+pub fn sort_records(records: &mut [StringRecord], keys: &[(usize, bool)], schema: &Schema) {
+    records.sort_by(|a, b| {
+        keys.iter()
+            .map(|&(col, descending)| {
+                let (a, b) = (
+                    a.get(col).unwrap_or_default(),
+                    b.get(col).unwrap_or_default(),
+                );
+                match (a.trim().is_empty(), b.trim().is_empty()) {
+                    (true, true) => Ordering::Equal,
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => {
+                        let ordering = compare(schema.value(col, a), schema.value(col, b));
+                        match descending {
+                            true => ordering.reverse(),
+                            false => ordering,
+                        }
+                    }
+                }
+            })
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Typed values come before text which didn't fit the column type.
+fn compare(a: Value, b: Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.total_cmp(&b),
+        (Value::Date(a), Value::Date(b)) => a.naive().cmp(&b.naive()),
+        (Value::Boolean(a), Value::Boolean(b)) => a.cmp(&b),
+        (Value::Text(a), Value::Text(b)) => collation_key(a)
+            .cmp(&collation_key(b))
+            .then_with(|| a.cmp(b)),
+        (Value::Text(_), _) => Ordering::Greater,
+        (_, Value::Text(_)) => Ordering::Less,
+        (a, b) => rank(a).cmp(&rank(b)),
+    }
+}
+
+fn rank(value: Value) -> u8 {
+    match value {
+        Value::Number(_) => 0,
+        Value::Date(_) => 1,
+        Value::Boolean(_) => 2,
+        Value::Text(_) => 3,
+    }
+}
+
+/// Lowercase text with accents removed, so `Äpfel` sorts next to `apfel` and not after `z`.
+fn collation_key(text: &str) -> String {
+    let mut key = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => key.push('a'),
+            'æ' => key.push_str("ae"),
+            'ç' | 'ć' | 'č' => key.push('c'),
+            'è' | 'é' | 'ê' | 'ë' => key.push('e'),
+            'ì' | 'í' | 'î' | 'ï' => key.push('i'),
+            'ñ' | 'ń' => key.push('n'),
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' => key.push('o'),
+            'œ' => key.push_str("oe"),
+            'ß' => key.push_str("ss"),
+            'š' | 'ś' => key.push('s'),
+            'ù' | 'ú' | 'û' | 'ü' => key.push('u'),
+            'ý' | 'ÿ' => key.push('y'),
+            'ž' | 'ź' | 'ż' => key.push('z'),
+            c => key.push(c),
+        }
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::{ColumnType, Separator};
+
+    /// Amount in German notation, day and name.
+    const ROWS: [[&str; 3]; 5] = [
+        ["1.234,5", "2.1.2024", "Zoe"],
+        ["", "1.1.2024 10:00", "anna"],
+        ["-7,25", "", "Äda"],
+        ["99", "1.1.2024", "Bernd"],
+        ["n/a", "31.12.2023", "ben"],
+    ];
+
+    fn schema() -> Schema {
+        Schema {
+            column_types: vec![
+                ColumnType::Decimal {
+                    separator: Separator::Comma,
+                    scale: 2,
+                },
+                ColumnType::DateTime,
+                ColumnType::Text,
+            ],
+            date_patterns: vec![vec!["%-d.%-m.%Y %H:%M".to_string(), "%-d.%-m.%Y".to_string()]; 3],
+        }
+    }
+
+    /// Names of the rows in sorted order.
+    fn sorted(rows: &[[&str; 3]], keys: &[(usize, bool)]) -> Vec<String> {
+        let mut records: Vec<StringRecord> = rows
+            .iter()
+            .map(|row| StringRecord::from(row.to_vec()))
+            .collect();
+        sort_records(&mut records, keys, &schema());
+        records.iter().map(|record| record[2].to_string()).collect()
+    }
+
+    #[test]
+    fn numbers_in_column_notation() {
+        assert_eq!(
+            sorted(&ROWS, &[(0, false)]),
+            vec!["Äda", "Bernd", "Zoe", "ben", "anna"]
+        );
+        assert_eq!(
+            sorted(&ROWS, &[(0, true)]),
+            vec!["ben", "Zoe", "Bernd", "Äda", "anna"]
+        );
+    }
+
+    #[test]
+    fn dates_in_time_order() {
+        assert_eq!(
+            sorted(&ROWS, &[(1, false)]),
+            vec!["ben", "Bernd", "anna", "Zoe", "Äda"]
+        );
+        assert_eq!(
+            sorted(&ROWS, &[(1, true)]),
+            vec!["Zoe", "anna", "Bernd", "ben", "Äda"]
+        );
+    }
+
+    #[test]
+    fn text_collated() {
+        assert_eq!(
+            sorted(&ROWS, &[(2, false)]),
+            vec!["Äda", "anna", "ben", "Bernd", "Zoe"]
+        );
+        assert_eq!(
+            sorted(&ROWS, &[(2, true)]),
+            vec!["Zoe", "Bernd", "ben", "anna", "Äda"]
+        );
+        let ties = [["", "", "Äpfel"], ["", "", "apfel"], ["", "", "Apfel"]];
+        assert_eq!(
+            sorted(&ties, &[(2, false)]),
+            vec!["Apfel", "apfel", "Äpfel"]
+        );
+    }
+
+    #[test]
+    fn later_keys_decide_ties_and_order_is_stable() {
+        let rows = [
+            ["1,5", "1.1.2024", "a"],
+            ["1,50", "2.1.2024", "b"],
+            ["1,5", "1.1.2024", "c"],
+            ["0,5", "1.1.2024", "d"],
+        ];
+        assert_eq!(sorted(&rows, &[(0, false)]), vec!["d", "a", "b", "c"]);
+        assert_eq!(
+            sorted(&rows, &[(0, true), (1, true)]),
+            vec!["b", "a", "c", "d"]
+        );
+    }
+}
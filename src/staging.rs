@@ -0,0 +1,193 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Bytes `.<name>.staging` adds to a name, the longest hidden sibling.
+pub const SIBLING_BYTES: usize = ".".len() + ".staging".len();
+
+/// Hidden folder next to the target folder which receives all files of a run.
+///
+/// It is renamed to the target by [`Staging::commit`] once every file is written, when
+/// appending its files are moved into the target.
+/// Dropping it without commit (failed or cancelled run) removes everything staged.
+pub struct Staging {
+    pub folder: PathBuf,
+    pub target: PathBuf,
+    /// Archive of the whole folder next to the target, if requested.
+    archive: Option<PathBuf>,
+    /// Whether the staged files are added to an existing target instead of replacing it.
+    append: bool,
+    committed: bool,
+}
+
+impl Staging {
+    /// Creates `.<target>.staging`, leftovers of an interrupted run are removed first.
+    ///
+    /// With `append` the files of an existing target are copied, so they can be appended.
+    pub fn new(target: PathBuf, archive: Option<PathBuf>, append: bool) -> io::Result<Staging> {
+        let staging = Staging {
+            folder: hidden_sibling(&target, "staging"),
+            target,
+            archive,
+            append,
+            committed: false,
+        };
+        staging.remove_staged();
+        fs::create_dir(&staging.folder)?;
+        if append && staging.target.is_dir() {
+            for entry in fs::read_dir(&staging.target)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    fs::copy(entry.path(), staging.folder.join(entry.file_name()))?;
+                }
+            }
+        }
+        Ok(staging)
+    }
+
+    /// Where the archive is written until the commit.
+    pub fn archive_path(&self) -> Option<PathBuf> {
+        self.archive
+            .as_ref()
+            .map(|archive| hidden_sibling(archive, "staging"))
+    }
+
+    /// Moves the staged folder and archive into place.
+    ///
+    /// An existing target is replaced, when appending only its staged files are so anything
+    /// else in it is kept. Without `keep_folder` only the archive is kept.
+    pub fn commit(mut self, keep_folder: bool) -> io::Result<()> {
+        if let (Some(staged), Some(archive)) = (self.archive_path(), &self.archive) {
+            replace(&staged, archive)?;
+        }
+        match keep_folder {
+            true if self.append && self.target.is_dir() => {
+                for entry in fs::read_dir(&self.folder)? {
+                    let entry = entry?;
+                    replace(&entry.path(), &self.target.join(entry.file_name()))?;
+                }
+                fs::remove_dir(&self.folder)?;
+            }
+            true => replace(&self.folder, &self.target)?,
+            false => fs::remove_dir_all(&self.folder)?,
+        }
+        self.committed = true;
+        Ok(())
+    }
+
+    fn remove_staged(&self) {
+        let _ = fs::remove_dir_all(&self.folder);
+        if let Some(staged) = self.archive_path() {
+            let _ = fs::remove_file(staged);
+        }
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.remove_staged();
+        }
+    }
+}
+
+/// `.<name>.<suffix>` in the same folder as `path`.
+fn hidden_sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Renames `from` to `to`. An existing `to` is moved aside first and removed afterwards.
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    if !to.exists() {
+        return fs::rename(from, to);
+    }
+    let old = hidden_sibling(to, "old");
+    fs::rename(to, &old)?;
+    fs::rename(from, to)?;
+    match old.is_dir() {
+        true => fs::remove_dir_all(old),
+        false => fs::remove_file(old),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(name: &str) -> PathBuf {
+        let target =
+            std::env::temp_dir().join(format!("csv-helper-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&target);
+        fs::create_dir_all(target.join("kept")).unwrap();
+        fs::write(target.join("kept").join("notes.txt"), "notes").unwrap();
+        fs::write(target.join("a.csv"), "a\n1\n").unwrap();
+        target
+    }
+
+    #[test]
+    fn append_keeps_other_content() {
+        let target = target("append");
+        let staging = Staging::new(target.clone(), None, true).unwrap();
+        let mut appended = fs::read_to_string(staging.folder.join("a.csv")).unwrap();
+        appended.push_str("2\n");
+        fs::write(staging.folder.join("a.csv"), appended).unwrap();
+        fs::write(staging.folder.join("b.csv"), "b\n").unwrap();
+        let folder = staging.folder.clone();
+        staging.commit(true).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(target.join("a.csv")).unwrap(),
+            "a\n1\n2\n"
+        );
+        assert!(target.join("b.csv").is_file());
+        assert!(target.join("kept").join("notes.txt").is_file());
+        assert!(!folder.exists());
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn overwrite_replaces_target() {
+        let target = target("overwrite");
+        let staging = Staging::new(target.clone(), None, false).unwrap();
+        assert!(!staging.folder.join("a.csv").exists());
+        fs::write(staging.folder.join("b.csv"), "b\n").unwrap();
+        staging.commit(true).unwrap();
+
+        assert!(target.join("b.csv").is_file());
+        assert!(!target.join("a.csv").exists() && !target.join("kept").exists());
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn failed_run_leaves_target_untouched() {
+        let target = target("failed");
+        let archive = target.with_extension("zip");
+        let staging = Staging::new(target.clone(), Some(archive.clone()), true).unwrap();
+        let (folder, staged_archive) = (staging.folder.clone(), staging.archive_path().unwrap());
+        fs::write(folder.join("a.csv"), "a\n1\n2\n").unwrap();
+        fs::write(&staged_archive, "zip").unwrap();
+        drop(staging);
+
+        assert!(!folder.exists() && !staged_archive.exists() && !archive.exists());
+        assert_eq!(fs::read_to_string(target.join("a.csv")).unwrap(), "a\n1\n");
+        assert!(target.join("kept").join("notes.txt").is_file());
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn archive_without_folder() {
+        let target = target("archive");
+        fs::remove_dir_all(&target).unwrap();
+        let archive = target.with_extension("zip");
+        let staging = Staging::new(target.clone(), Some(archive.clone()), false).unwrap();
+        let folder = staging.folder.clone();
+        fs::write(staging.archive_path().unwrap(), "zip").unwrap();
+        staging.commit(false).unwrap();
+
+        assert_eq!(fs::read_to_string(&archive).unwrap(), "zip");
+        assert!(!folder.exists() && !target.exists());
+        fs::remove_file(archive).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+
+use csv::StringRecord;
+
+use crate::{
+    infer::{ColumnType, Schema, Value},
+    utils::{try_parse_time, ParsedTime},
+};
+
+/// Statistics of all categories, written to the output folder next to the category files.
+pub const STATISTICS_CSV: &str = "summary.csv";
+pub const STATISTICS_XLSX: &str = "summary.xlsx";
+
+/// Aggregates of one column within one category. Empty fields are ignored.
+///
+/// Fields are counted while the input is read, numbers and dates are read once the column
+/// type is known, see [`Statistics::finish`].
+#[derive(Clone, Debug, Default)]
+pub struct ColumnStats {
+    /// Non-empty values.
+    pub count: usize,
+    /// Occurrences of each value until the statistics are finished.
+    values: HashMap<String, usize>,
+    distinct: usize,
+    /// Values read as number of the column type, sum and average only include these.
+    numbers: usize,
+    sum: f64,
+    number_range: Option<(f64, f64)>,
+    date_range: Option<(ParsedTime, ParsedTime)>,
+}
+
+impl ColumnStats {
+    fn add(&mut self, field: &str) {
+        if field.trim().is_empty() {
+            return;
+        }
+        self.count += 1;
+        match self.values.get_mut(field) {
+            Some(count) => *count += 1,
+            None => {
+                self.values.insert(field.to_string(), 1);
+            }
+        }
+    }
+
+    fn remove(&mut self, field: &str) {
+        if let Some(count) = self.values.get_mut(field) {
+            self.count -= 1;
+            *count -= 1;
+            if *count == 0 {
+                self.values.remove(field);
+            }
+        }
+    }
+
+    fn finish(&mut self, column_type: ColumnType, date_patterns: &[String]) {
+        let mut values: Vec<(String, usize)> = self.values.drain().collect();
+        // sums don't depend on the hash order
+        values.sort_unstable();
+        self.distinct += values.len();
+        for (field, count) in values {
+            if let Value::Number(number) = column_type.parse(&field) {
+                self.numbers += count;
+                self.sum += number * count as f64;
+                self.number_range = Some(match self.number_range {
+                    Some((min, max)) => (min.min(number), max.max(number)),
+                    None => (number, number),
+                });
+            } else if let Ok(time) = try_parse_time(&field, date_patterns) {
+                self.date_range = Some(match self.date_range {
+                    Some((min, max)) => (
+                        match time.naive() < min.naive() {
+                            true => time,
+                            false => min,
+                        },
+                        match time.naive() > max.naive() {
+                            true => time,
+                            false => max,
+                        },
+                    ),
+                    None => (time, time),
+                });
+            }
+        }
+    }
+
+    /// `None` without numbers.
+    pub fn sum(&self) -> Option<f64> {
+        Some(self.sum).filter(|_| self.numbers > 0)
+    }
+
+    pub fn average(&self) -> Option<f64> {
+        self.sum().map(|sum| sum / self.numbers as f64)
+    }
+
+    /// Smallest number, or the earliest date if the column has no numbers.
+    pub fn min(&self) -> Option<Value<'static>> {
+        match (self.number_range, self.date_range) {
+            (Some((min, _)), _) => Some(Value::Number(min)),
+            (None, Some((min, _))) => Some(Value::Date(min)),
+            (None, None) => None,
+        }
+    }
+
+    /// Largest number, or the latest date if the column has no numbers.
+    pub fn max(&self) -> Option<Value<'static>> {
+        match (self.number_range, self.date_range) {
+            (Some((_, max)), _) => Some(Value::Number(max)),
+            (None, Some((_, max))) => Some(Value::Date(max)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn distinct(&self) -> usize {
+        self.distinct
+    }
+}
+
+/// Aggregates of the statistics columns per category, collected while the input is read.
+pub struct Statistics {
+    /// Position of each statistics column in the input records and in the schema.
+    columns: Vec<(usize, usize)>,
+    categories: HashMap<String, Vec<ColumnStats>>,
+}
+
+impl Statistics {
+    pub fn new(columns: Vec<(usize, usize)>) -> Statistics {
+        Statistics {
+            columns,
+            categories: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, category: &str, record: &StringRecord) {
+        if !self.categories.contains_key(category) {
+            let stats = vec![ColumnStats::default(); self.columns.len()];
+            self.categories.insert(category.to_string(), stats);
+        }
+        let stats = self.categories.get_mut(category).unwrap();
+        for (stats, &(col, _)) in stats.iter_mut().zip(self.columns.iter()) {
+            stats.add(record.get(col).unwrap_or_default());
+        }
+    }
+
+    /// Takes back a record added before, like a row replaced by a later duplicate.
+    pub fn remove(&mut self, category: &str, record: &StringRecord) {
+        if let Some(stats) = self.categories.get_mut(category) {
+            for (stats, &(col, _)) in stats.iter_mut().zip(self.columns.iter()) {
+                stats.remove(record.get(col).unwrap_or_default());
+            }
+        }
+    }
+
+    /// Reads numbers and dates with the column types of the schema, so they match the
+    /// written cells.
+    pub fn finish(&mut self, schema: &Schema) {
+        for stats in self.categories.values_mut() {
+            for (stats, &(_, col)) in stats.iter_mut().zip(self.columns.iter()) {
+                stats.finish(schema.column_type(col), &schema.date_patterns[col]);
+            }
+        }
+    }
+
+    pub fn take(&mut self, category: &str) -> Vec<ColumnStats> {
+        self.categories.remove(category).unwrap_or_default()
+    }
+}
+
+/// Value as written to the CSV summary and shown in the wizard.
+pub fn format_value(value: Value) -> String {
+    match value {
+        Value::Number(number) => format_number(number),
+        Value::Date(time) => time.to_iso(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Text(text) => text.to_string(),
+    }
+}
+
+/// Rounded to 6 fraction digits to hide floating point noise of sums.
+pub fn format_number(number: f64) -> String {
+    let rounded = format!("{:.6}", number);
+    rounded
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::Separator;
+
+    /// Adds the fields of `add` and removes those of `remove` like `read_csv`, then finishes
+    /// with the column type.
+    fn column_stats(column_type: ColumnType, add: &[&str], remove: &[&str]) -> ColumnStats {
+        let schema = Schema {
+            column_types: vec![ColumnType::Text, column_type],
+            date_patterns: vec![vec![], vec!["%Y-%m-%d".to_string()]],
+        };
+        // the input has an extra first column which isn't written
+        let mut statistics = Statistics::new(vec![(2, 1)]);
+        let record = |field: &str| StringRecord::from(vec!["x", "y", field]);
+        for field in add {
+            statistics.add("a", &record(field));
+        }
+        for field in remove {
+            statistics.remove("a", &record(field));
+        }
+        statistics.finish(&schema);
+        statistics.take("a").remove(0)
+    }
+
+    #[test]
+    fn numbers_follow_the_column_type() {
+        let english = ColumnType::Decimal {
+            separator: Separator::Point,
+            scale: 3,
+        };
+        let stats = column_stats(english, &["1.5", "2.25", "1.234", "", "1.5"], &[]);
+        assert_eq!((stats.count, stats.distinct()), (4, 3));
+        assert_eq!(stats.sum().map(format_number).as_deref(), Some("6.484"));
+        assert_eq!(stats.average().map(format_number).as_deref(), Some("1.621"));
+        assert_eq!(stats.min(), Some(Value::Number(1.234)));
+        assert_eq!(stats.max(), Some(Value::Number(2.25)));
+
+        let german = ColumnType::Decimal {
+            separator: Separator::Comma,
+            scale: 2,
+        };
+        let stats = column_stats(german, &["1.234,5", "0,5", "n/a"], &[]);
+        assert_eq!((stats.count, stats.distinct()), (3, 3));
+        assert_eq!(stats.sum().map(format_number).as_deref(), Some("1235"));
+        assert_eq!(stats.average(), Some(617.5));
+        assert_eq!(stats.min(), Some(Value::Number(0.5)));
+        assert_eq!(stats.max(), Some(Value::Number(1234.5)));
+    }
+
+    #[test]
+    fn dates_and_text() {
+        let stats = column_stats(
+            ColumnType::Date,
+            &["2024-03-05", "2023-12-31", "x", "2024-03-05"],
+            &[],
+        );
+        assert_eq!((stats.count, stats.distinct()), (4, 3));
+        assert_eq!((stats.sum(), stats.average()), (None, None));
+        assert_eq!(stats.min().map(format_value).as_deref(), Some("2023-12-31"));
+        assert_eq!(stats.max().map(format_value).as_deref(), Some("2024-03-05"));
+
+        let stats = column_stats(ColumnType::Text, &["12", "b", "a"], &[]);
+        assert_eq!((stats.count, stats.distinct()), (3, 3));
+        assert_eq!((stats.sum(), stats.min()), (None, None));
+    }
+
+    #[test]
+    fn removed_rows_leave_the_statistics() {
+        let stats = column_stats(ColumnType::Integer, &["3", "5", "5", "9"], &["5", "9"]);
+        assert_eq!((stats.count, stats.distinct()), (2, 2));
+        assert_eq!(stats.sum(), Some(8.0));
+        assert_eq!(stats.max(), Some(Value::Number(5.0)));
+
+        let stats = column_stats(
+            ColumnType::Date,
+            &["2024-03-05", "2023-12-31"],
+            &["2023-12-31"],
+        );
+        assert_eq!(stats.min().map(format_value).as_deref(), Some("2024-03-05"));
+    }
+}
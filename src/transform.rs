@@ -1,31 +1,459 @@
-use csv::{StringRecord, WriterBuilder};
-use std::{collections::HashMap, error::Error, path::PathBuf, vec};
+use chrono::{DateTime, Local};
+use csv::{QuoteStyle, StringRecord, Terminator, WriterBuilder};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    vec,
+};
 
+use arrow_ipc::writer::FileWriter;
 use cursive::{views::TextView, CbSink, Cursive};
-use xlsxwriter::Workbook;
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{Compression, GzipLevel, ZstdLevel},
+    file::properties::WriterProperties,
+};
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection};
+use rust_xlsxwriter::{Format, Table, TableColumn, TableStyle, Workbook};
+use serde_json::{json, Map, Value as JsonValue};
 
 use crate::{
-    errors::DirectoryError,
-    utils::{replace_all_invalid_characters, try_parse_time, Header},
+    archive::write_zip,
+    chunk::{chunk_bytes, chunk_rows, part_stem},
+    clean::{clean_record, column_steps},
+    columnar::{arrow_schema, record_batch},
+    crosstab::{Crosstab, CROSSTAB_FILE},
+    dedup::{Deduplicator, DUPLICATES_FILE},
+    errors::{
+        AppendError, ArchiveError, CancelError, CleaningError, DirectoryError, ExpressionError,
+        HeaderError, HeaderMismatchError, LookupError, MaskingError,
+    },
+    expr::parse_derived_columns,
+    infer::{infer_column_types, ColumnType, Schema, Value},
+    lookup::Lookup,
+    manifest::{write_manifest, MANIFEST_FILE},
+    mask::Masker,
+    naming::{render, NameContext, FILE_TEMPLATE, FOLDER_TEMPLATE},
+    sort::sort_records,
+    staging::{Staging, SIBLING_BYTES},
+    stats::{
+        format_number, format_value, ColumnStats, Statistics, STATISTICS_CSV, STATISTICS_XLSX,
+    },
+    utils::{
+        excel_table_name, is_formula, replace_all_invalid_characters, truncate_name,
+        unique_headers, Header, ParsedTime, CSV_DELIMITERS, CSV_LINE_ENDINGS, CSV_QUOTE_STYLES,
+        DEFAULT_DATE_PATTERNS, EXCEL_DATETIME_FORMATS, EXCEL_DATE_FORMATS, EXCEL_TABLE_STYLES,
+        UTF8_BOM,
+    },
 };
 
 pub struct Transformer {
     pub sink: CbSink,
     options: Options,
     headers: StringRecord,
+    /// Time of the run, used for names and metadata.
+    started: DateTime<Local>,
+    /// Folder receiving the files during the run.
+    staging: Option<Staging>,
+    /// Set by the user to stop the run, checked with every progress update.
+    cancel: Arc<AtomicBool>,
+    /// Position of the category column in the output, `None` if it is dropped.
+    category_column: Option<usize>,
+    /// Output columns with their input names, for settings per column.
+    source_headers: StringRecord,
+    /// Cells neutralized by [`FormulaPolicy::Escape`] so far.
+    formula_cells: Vec<FormulaCell>,
 }
 
 #[derive(Clone, Debug)]
 pub struct Options {
+    /// Empty to write all rows to one file, which is then split into parts.
     selected_category: String,
     pub input: PathBuf,
     pub output: PathBuf,
     filter: Option<(String, String)>,
+    infer_types: bool,
+    /// One chrono pattern per entry, `Column = pattern` restricts it to a single column.
+    date_patterns: Vec<String>,
+    excel_date_format: String,
+    excel_datetime_format: String,
+    /// Label of an entry in [`EXCEL_TABLE_STYLES`], `None` writes a plain range.
+    excel_table_style: Option<String>,
+    csv_dialect: CsvDialect,
+    csv_formula_policy: FormulaPolicy,
+    xlsx_formula_policy: FormulaPolicy,
+    formats: Vec<OutputFormat>,
+    sqlite_layout: SqliteLayout,
+    /// Label of an entry in [`PARQUET_COMPRESSIONS`].
+    parquet_compression: String,
+    parquet_row_group_size: usize,
+    archive_mode: ArchiveMode,
+    /// 0 (stored) to 9 (smallest).
+    archive_level: i64,
+    existing_policy: ExistingPolicy,
+    /// Output columns in order, empty for all input columns.
+    columns: Vec<String>,
+    /// Drop the category and filter columns, which are constant within a file.
+    drop_category_columns: bool,
+    /// Input header and its name in the output.
+    header_renames: Vec<(String, String)>,
+    /// `Column = step, ...` lines applied to the input fields, see [`crate::clean`].
+    cleaning_rules: Vec<String>,
+    /// Reference CSV joined to the input, see [`crate::lookup`].
+    lookup_file: Option<PathBuf>,
+    /// Input header and reference header compared by the join.
+    lookup_keys: (String, String),
+    lookup_join: JoinKind,
+    /// Name and expression of columns added to every record, see [`crate::expr`].
+    derived_columns: Vec<(String, String)>,
+    /// `Column = mask` lines, see [`crate::mask`].
+    masks: Vec<String>,
+    /// Secret prepended to hashed values, the same salt gives the same hashes.
+    mask_salt: String,
+    /// Input header and descending, rows of each category are sorted by these in order.
+    sort_keys: Vec<(String, bool)>,
+    /// Output columns aggregated per category, see [`crate::stats`].
+    statistics_columns: Vec<String>,
+    /// Output column cross-tabulated against the categories, see [`crate::crosstab`].
+    crosstab_column: Option<String>,
+    /// Output column summed in the crosstab, `None` counts rows.
+    crosstab_sum: Option<String>,
+    dedup_scope: DedupScope,
+    /// Input headers compared to find duplicates, empty for complete rows.
+    dedup_keys: Vec<String>,
+    dedup_keep_last: bool,
+    /// Write removed rows to [`DUPLICATES_FILE`].
+    write_duplicates: bool,
+    /// Naming templates, see [`crate::naming`].
+    folder_template: String,
+    file_template: String,
+    /// Write [`MANIFEST_FILE`] with checksums of all files.
+    manifest: bool,
+    /// Splits every category file into parts, see [`crate::chunk`].
+    chunk_mode: ChunkMode,
+    /// Rows or bytes per part.
+    chunk_size: usize,
+}
+
+/// Dialect of the written CSV files, independent of the input file.
+///
+/// Fields are labels of [`CSV_DELIMITERS`], [`CSV_QUOTE_STYLES`] and [`CSV_LINE_ENDINGS`].
+#[derive(Clone, Debug)]
+pub struct CsvDialect {
+    pub delimiter: String,
+    pub quote_style: String,
+    pub line_ending: String,
+    /// Byte order mark, required by Excel on Windows to detect UTF-8.
+    pub bom: bool,
+}
+
+/// Formats written for every category.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Csv,
+    Xlsx,
+    Json,
+    Ndjson,
+    /// One database for all categories, see [`SqliteLayout`].
+    Sqlite,
+    Parquet,
+    /// Arrow IPC file format
+    Arrow,
+}
+
+pub const OUTPUT_FORMATS: [OutputFormat; 7] = [
+    OutputFormat::Csv,
+    OutputFormat::Xlsx,
+    OutputFormat::Json,
+    OutputFormat::Ndjson,
+    OutputFormat::Sqlite,
+    OutputFormat::Parquet,
+    OutputFormat::Arrow,
+];
+
+/// Compression codecs offered for Parquet, the first one is the default.
+pub const PARQUET_COMPRESSIONS: [&str; 4] = ["Snappy", "Zstd", "Gzip", "Uncompressed"];
+
+/// Rows per part offered by default, a common upload limit.
+const CHUNK_ROWS: usize = 50_000;
+
+/// Default of the parquet crate (1024 * 1024 rows).
+const PARQUET_ROW_GROUP_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SqliteLayout {
+    TablePerCategory,
+    /// All records in one table with an index on the category column.
+    SingleTable,
+}
+
+pub const SQLITE_LAYOUTS: [SqliteLayout; 2] =
+    [SqliteLayout::TablePerCategory, SqliteLayout::SingleTable];
+
+impl SqliteLayout {
+    pub fn label(self) -> &'static str {
+        match self {
+            SqliteLayout::TablePerCategory => "Table per category",
+            SqliteLayout::SingleTable => "Single table",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveMode {
+    None,
+    /// `<folder>.zip` next to the output folder.
+    Alongside,
+    /// Only `<folder>.zip`, the output folder is removed.
+    Instead,
+    /// One `<category>.zip` with all formats of the category inside the output folder.
+    PerCategory,
+}
+
+pub const ARCHIVE_MODES: [ArchiveMode; 4] = [
+    ArchiveMode::None,
+    ArchiveMode::Alongside,
+    ArchiveMode::Instead,
+    ArchiveMode::PerCategory,
+];
+
+impl ArchiveMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            ArchiveMode::None => "No archive",
+            ArchiveMode::Alongside => "ZIP next to folder",
+            ArchiveMode::Instead => "ZIP instead of folder",
+            ArchiveMode::PerCategory => "ZIP per category",
+        }
+    }
+}
+
+/// Which rows are compared to find duplicates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DedupScope {
+    Off,
+    /// Rows of all categories.
+    Global,
+    /// Only rows of the same category.
+    PerCategory,
+}
+
+pub const DEDUP_SCOPES: [DedupScope; 3] =
+    [DedupScope::Off, DedupScope::Global, DedupScope::PerCategory];
+
+impl DedupScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            DedupScope::Off => "Keep duplicates",
+            DedupScope::Global => "Across all categories",
+            DedupScope::PerCategory => "Within each category",
+        }
+    }
+}
+
+/// Which input rows are kept by the lookup.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinKind {
+    /// All rows, reference columns stay empty without match.
+    Left,
+    /// Only rows with a match.
+    Inner,
+}
+
+pub const JOIN_KINDS: [JoinKind; 2] = [JoinKind::Left, JoinKind::Inner];
+
+impl JoinKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            JoinKind::Left => "Keep unmatched rows",
+            JoinKind::Inner => "Drop unmatched rows",
+        }
+    }
+}
+
+/// Limit of the parts every category file is split into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChunkMode {
+    Off,
+    Rows,
+    /// Bytes of the CSV file, the other formats get the same rows per part.
+    Bytes,
+}
+
+pub const CHUNK_MODES: [ChunkMode; 3] = [ChunkMode::Off, ChunkMode::Rows, ChunkMode::Bytes];
+
+impl ChunkMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            ChunkMode::Off => "One file per category",
+            ChunkMode::Rows => "Parts of at most N rows",
+            ChunkMode::Bytes => "Parts of at most N bytes",
+        }
+    }
+}
+
+/// Handling of values starting like a formula, e.g. `=HYPERLINK(...)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormulaPolicy {
+    /// CSV values get a leading `'`, Excel cells the quote prefix, so they stay text.
+    Escape,
+    Keep,
+}
+
+pub const FORMULA_POLICIES: [FormulaPolicy; 2] = [FormulaPolicy::Escape, FormulaPolicy::Keep];
+
+impl FormulaPolicy {
+    pub fn label(self) -> &'static str {
+        match self {
+            FormulaPolicy::Escape => "Neutralize",
+            FormulaPolicy::Keep => "Keep as is",
+        }
+    }
+}
+
+/// What happens if the output folder already exists.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExistingPolicy {
+    /// Abort with [`DirectoryError`].
+    Fail,
+    /// Remove the existing folder first.
+    Overwrite,
+    /// Write to `<folder>_<yyyymmdd-hhmmss>` instead.
+    Timestamp,
+    /// Write to `<folder>_2`, `<folder>_3`, ... instead.
+    Number,
+    /// Append rows to existing category files with the same headers.
+    Append,
+}
+
+pub const EXISTING_POLICIES: [ExistingPolicy; 5] = [
+    ExistingPolicy::Fail,
+    ExistingPolicy::Overwrite,
+    ExistingPolicy::Timestamp,
+    ExistingPolicy::Number,
+    ExistingPolicy::Append,
+];
+
+impl ExistingPolicy {
+    pub fn label(self) -> &'static str {
+        match self {
+            ExistingPolicy::Fail => "Fail",
+            ExistingPolicy::Overwrite => "Overwrite",
+            ExistingPolicy::Timestamp => "New folder with timestamp",
+            ExistingPolicy::Number => "New numbered folder",
+            ExistingPolicy::Append => "Append to files",
+        }
+    }
+}
+
+/// Deflate level used by default, same as zlib.
+const ARCHIVE_LEVEL: i64 = 6;
+
+/// Name of the table used by [`SqliteLayout::SingleTable`].
+const SQLITE_RECORDS_TABLE: &str = "records";
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Sqlite => "sqlite",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
+        }
+    }
+
+    /// Formats whose files can be extended by [`ExistingPolicy::Append`].
+    pub fn is_appendable(self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Csv | OutputFormat::Json | OutputFormat::Ndjson
+        )
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "CSV",
+            OutputFormat::Xlsx => "Excel",
+            OutputFormat::Json => "JSON",
+            OutputFormat::Ndjson => "NDJSON",
+            OutputFormat::Sqlite => "SQLite",
+            OutputFormat::Parquet => "Parquet",
+            OutputFormat::Arrow => "Arrow",
+        }
+    }
+}
+
+/// Statistics of a finished run.
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub cat_total: i32,
+    pub csv_rl: i32,
+    /// Written lines per format, without header.
+    pub written: Vec<(OutputFormat, i32)>,
+    pub files: Vec<OutputFile>,
+    /// Category value and its file name without extension.
+    pub file_names: Vec<(String, String)>,
+    /// Removed duplicates, `None` without deduplication.
+    pub duplicates: Option<usize>,
+    /// Lookup keys without reference row and their number of rows, `None` without lookup.
+    pub unmatched_keys: Option<Vec<(String, usize)>>,
+    /// Category with the aggregates of every statistics column by output name.
+    pub statistics: Vec<(String, Vec<(String, ColumnStats)>)>,
+    pub formula_cells: Vec<FormulaCell>,
+}
+
+/// Cell which would have been evaluated as formula by a spreadsheet application.
+#[derive(Clone, Debug)]
+pub struct FormulaCell {
+    pub file: String,
+    /// Row written by this run, starting at 1 below the header. Values of the crosstab
+    /// column are in row 0, as they make up the header of the crosstab.
+    pub row: usize,
+    pub column: String,
+}
+
+/// File created in the output folder.
+#[derive(Clone, Debug)]
+pub struct OutputFile {
+    pub path: PathBuf,
+    /// Category value, `None` for files with all categories.
+    pub category: Option<String>,
+    /// Rows written by this run, without header.
+    pub rows: usize,
 }
 
-/// (csv_rl, cat_total, HashMap<category_key.lowercase, ([Records], first_cat_name))
-type CsvReadResult =
-    Result<(i32, i32, HashMap<String, (Vec<StringRecord>, String)>), Box<dyn Error>>;
+/// Header of the statistics files.
+const STATISTICS_HEADERS: [&str; 8] = [
+    "Category", "Column", "Values", "Sum", "Average", "Min", "Max", "Distinct",
+];
+
+/// HashMap<category_key.lowercase, ([Records], first_cat_name)>
+type Categories = HashMap<String, (Vec<StringRecord>, String)>;
+
+/// (csv_rl, cat_total, Categories, Deduplicator, Lookup, Statistics)
+type CsvReadResult = Result<
+    (
+        i32,
+        i32,
+        Categories,
+        Option<Deduplicator>,
+        Option<Lookup>,
+        Option<Statistics>,
+    ),
+    Box<dyn Error>,
+>;
 
 impl Transformer {
     pub fn new(sink: CbSink, options: Options, headers: StringRecord) -> Transformer {
@@ -33,10 +461,23 @@ impl Transformer {
             sink,
             options,
             headers,
+            started: Local::now(),
+            staging: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+            category_column: None,
+            source_headers: StringRecord::new(),
+            formula_cells: vec![],
         }
     }
 
-    fn write_to_running_view(&mut self, text: String) {
+    pub fn get_cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
+    }
+
+    fn write_to_running_view(&mut self, text: String) -> Result<(), Box<dyn Error>> {
+        if self.cancel.load(Ordering::Relaxed) {
+            return Err(Box::new(CancelError));
+        }
         self.sink
             .send(Box::new(move |s: &mut Cursive| {
                 s.call_on_name("running", |view: &mut TextView| {
@@ -44,6 +485,7 @@ impl Transformer {
                 });
             }))
             .unwrap();
+        Ok(())
     }
 
     /// Execute will read a csv and then write to files by category and filter (optional).
@@ -51,82 +493,419 @@ impl Transformer {
     /// The value in the Hashmap is (Records, first field name for that category) -> Background: Windows doesn't differentiate between upper and lowercase.
     /// Hence test.csv and Test.csv would overwrite each other and corrupt the result.
     ///
-    /// Returns Ok(cat_total, csv_rl, csv_wl, excel_wl)
-    pub fn execute(&mut self) -> Result<(i32, i32, i32, i32), Box<dyn Error>> {
+    /// All files are staged and only moved into place if every write succeeded.
+    pub fn execute(&mut self) -> Result<Summary, Box<dyn Error>> {
+        let result = self.transform();
+        // Removes the staged files if the run failed.
+        self.staging = None;
+        result
+    }
+
+    fn transform(&mut self) -> Result<Summary, Box<dyn Error>> {
         match csv::ReaderBuilder::new()
             .delimiter(b';')
             .from_path(&self.options.input)
         {
             Ok(rdr) => {
-                let (csv_rl, cat_total, categories) = self.read_csv(rdr)?;
+                if self.options.existing_policy == ExistingPolicy::Append
+                    && !self.options.can_append()
+                {
+                    return Err(Box::new(AppendError));
+                }
+                let (csv_rl, cat_total, mut categories, dedup, lookup, mut statistics) =
+                    self.read_csv(rdr)?;
 
-                let (mut csv_wl, mut excel_wl) = (0, 0);
+                let mut summary = Summary {
+                    cat_total,
+                    csv_rl,
+                    written: self
+                        .options
+                        .formats
+                        .iter()
+                        .map(|format| (*format, 0))
+                        .collect(),
+                    files: vec![],
+                    file_names: vec![],
+                    duplicates: dedup.as_ref().map(|dedup| dedup.removed),
+                    unmatched_keys: lookup.map(Lookup::unmatched),
+                    formula_cells: vec![],
+                    statistics: vec![],
+                };
 
-                self.create_dir_for_csv_and_xslx()?;
+                self.create_dir_for_csv_and_xslx(csv_rl as usize)?;
 
-                for (records, category_sub_collection) in categories.values() {
-                    let (path_csv, path_xlsx) =
-                        self.get_csv_xlsx_path(category_sub_collection.to_string());
-                    self.write_csv(path_csv, records, &mut csv_wl)?;
-                    self.write_xlsx(path_xlsx, records, &mut excel_wl)?;
+                let mut schema = self.infer_schema(&categories, self.options.infer_types);
+                if let Some(statistics) = statistics.as_mut() {
+                    statistics.finish(&self.typed_schema(&categories, &schema));
+                }
+                self.sort_categories(&mut categories, &schema)?;
+                let crosstab = self.crosstab(&categories, &schema)?;
+                // masked values may no longer fit the types of the clear values
+                if self.mask_categories(&mut categories)? {
+                    schema = self.infer_schema(&categories, self.options.infer_types);
+                }
+
+                let mut sorted: Vec<_> = categories.values().collect();
+                sorted.sort_by(|(_, a), (_, b)| a.cmp(b));
+                let parts: Vec<_> = sorted
+                    .iter()
+                    .map(|(records, _)| self.chunks(records))
+                    .collect();
+                let file_stems = self.get_file_stems(&sorted, &parts);
+                for (((records, category_sub_collection), file_stem), parts) in
+                    sorted.into_iter().zip(file_stems).zip(parts)
+                {
+                    summary
+                        .file_names
+                        .push((category_sub_collection.clone(), file_stem.clone()));
+                    if let Some(statistics) = statistics.as_mut() {
+                        let stats = statistics.take(&category_sub_collection.to_lowercase());
+                        let columns = self
+                            .options
+                            .statistics_columns
+                            .iter()
+                            .map(|column| self.options.get_header_name(column));
+                        summary.statistics.push((
+                            category_sub_collection.clone(),
+                            columns.zip(stats).collect(),
+                        ));
+                    }
+                    let category = Some(category_sub_collection.clone())
+                        .filter(|_| !self.options.selected_category.is_empty());
+                    let mut category_files = vec![];
+                    for (part_stem, rows) in
+                        self.part_stems(&file_stem, parts.len()).iter().zip(parts)
+                    {
+                        // without parts the records are written as they are
+                        let records = match self.options.chunk_mode {
+                            ChunkMode::Off => Cow::Borrowed(records),
+                            _ => Cow::Owned(
+                                std::iter::once(&records[0])
+                                    .chain(&records[rows])
+                                    .cloned()
+                                    .collect(),
+                            ),
+                        };
+                        for (format, lines) in summary.written.iter_mut() {
+                            if *format == OutputFormat::Sqlite {
+                                continue;
+                            }
+                            let path = self.get_output_path(part_stem, *format);
+                            category_files.push(OutputFile {
+                                path: path.clone(),
+                                category: category.clone(),
+                                rows: records.len() - 1,
+                            });
+                            match format {
+                                OutputFormat::Csv => self.write_csv(path, &records, lines)?,
+                                OutputFormat::Xlsx => self.write_xlsx(
+                                    path,
+                                    &records,
+                                    &schema,
+                                    &excel_table_name(category_sub_collection),
+                                    lines,
+                                )?,
+                                OutputFormat::Json | OutputFormat::Ndjson => {
+                                    self.write_json(path, &records, &schema, *format, lines)?
+                                }
+                                OutputFormat::Parquet | OutputFormat::Arrow => {
+                                    self.write_columnar(path, &records, &schema, *format, lines)?
+                                }
+                                OutputFormat::Sqlite => unreachable!(),
+                            }
+                        }
+                    }
+                    if self.options.archive_mode == ArchiveMode::PerCategory {
+                        let path = self.folder().join(format!("{}.zip", file_stem));
+                        self.write_category_zip(path.clone(), &category_files)?;
+                        category_files = vec![OutputFile {
+                            path,
+                            category,
+                            rows: records.len() - 1,
+                        }];
+                    }
+                    summary.files.append(&mut category_files);
+                }
+
+                if let Some((_, lines)) = summary
+                    .written
+                    .iter_mut()
+                    .find(|(format, _)| *format == OutputFormat::Sqlite)
+                {
+                    let folder_name = self.options.output.file_name().unwrap().to_owned();
+                    let path = self
+                        .folder()
+                        .join(folder_name)
+                        .with_extension(OutputFormat::Sqlite.extension());
+                    self.write_sqlite(path.clone(), &categories, &schema, lines)?;
+                    summary.files.push(OutputFile {
+                        path,
+                        category: None,
+                        rows: *lines as usize,
+                    });
+                }
+
+                if let Some(duplicates) = dedup.as_ref().and_then(Deduplicator::duplicates) {
+                    let path = self.folder().join(DUPLICATES_FILE);
+                    self.write_duplicates(&path, duplicates)?;
+                    summary.files.push(OutputFile {
+                        path,
+                        category: None,
+                        rows: duplicates.len() - 1,
+                    });
+                }
+
+                if !summary.statistics.is_empty() {
+                    let path = self.folder().join(STATISTICS_CSV);
+                    self.write_statistics_csv(&path, &summary.statistics)?;
+                    let rows = summary.statistics.len() * self.options.statistics_columns.len();
+                    summary.files.push(OutputFile {
+                        path,
+                        category: None,
+                        rows,
+                    });
+                    let path = self.folder().join(STATISTICS_XLSX);
+                    self.write_statistics_xlsx(&path, &summary.statistics)?;
+                    summary.files.push(OutputFile {
+                        path,
+                        category: None,
+                        rows,
+                    });
+                }
+
+                if let Some(crosstab) = crosstab {
+                    let path = self.folder().join(CROSSTAB_FILE);
+                    self.write_crosstab(&path, &crosstab)?;
+                    summary.files.push(OutputFile {
+                        path,
+                        category: None,
+                        rows: crosstab.rows.len(),
+                    });
+                }
+
+                if self.options.manifest {
+                    self.write_to_running_view(format!("Writing {}", MANIFEST_FILE))?;
+                    write_manifest(
+                        self.folder(),
+                        &self.options.input,
+                        self.options.to_json(),
+                        &summary.files,
+                        self.started,
+                    )?;
+                }
+
+                self.write_folder_zip()?;
+
+                self.write_to_running_view("Moving files into place".to_string())?;
+                let staging = self.staging.take().unwrap();
+                staging.commit(self.options.archive_mode != ArchiveMode::Instead)?;
+                for file in summary.files.iter_mut() {
+                    file.path = self.options.output.join(file.path.file_name().unwrap());
                 }
+                summary.formula_cells = std::mem::take(&mut self.formula_cells);
 
-                Ok((cat_total as i32, csv_rl as i32, csv_wl, excel_wl))
+                Ok(summary)
             }
             Err(error) => Err(Box::new(error)),
         }
     }
 
-    fn create_dir_for_csv_and_xslx(&mut self) -> Result<(), Box<dyn Error>> {
-        self.options.output = self.options.output.join(match &self.options.filter {
-            Some((filter_field, filter_value)) => replace_all_invalid_characters(&format!(
-                "{}_{}_{}",
-                self.options.selected_category, filter_field, filter_value
-            )),
-            None => replace_all_invalid_characters(&self.options.selected_category),
-        });
-        if self.options.output.exists() {
-            Err(Box::new(DirectoryError))
-        } else {
-            std::fs::create_dir(self.options.output.as_path())?;
-            Ok(())
+    /// Creates the output folder named by the folder template.
+    fn create_dir_for_csv_and_xslx(&mut self, csv_rl: usize) -> Result<(), Box<dyn Error>> {
+        let category = match self.options.selected_category.is_empty() {
+            true => self.get_input_stem(),
+            false => self.options.selected_category.clone(),
+        };
+        let folder_name = render(
+            &self.options.folder_template,
+            &NameContext {
+                category: &category,
+                filter: self.options.filter.as_ref(),
+                input_stem: &self.get_input_stem(),
+                rowcount: csv_rl,
+                index: None,
+                date: self.started,
+            },
+        );
+        let folder_name = replace_all_invalid_characters(&folder_name);
+        // the staged archive `.<folder>.zip.staging` is the longest name derived from it
+        let reserved = ".zip".len() + SIBLING_BYTES;
+        let sibling = |suffix: String| {
+            let name = truncate_name(&folder_name, reserved + suffix.len());
+            self.options.output.join(format!("{}{}", name, suffix))
+        };
+        let folder = sibling(String::new());
+        let taken = |folder: &Path| {
+            folder.exists()
+                || self
+                    .get_folder_zip_path(folder)
+                    .is_some_and(|path| path.exists())
+        };
+
+        self.options.output = match self.options.existing_policy {
+            ExistingPolicy::Fail if folder.exists() => return Err(Box::new(DirectoryError)),
+            ExistingPolicy::Fail if taken(&folder) => return Err(Box::new(ArchiveError)),
+            ExistingPolicy::Timestamp if taken(&folder) => {
+                let stamp = format!("_{}", self.started.format("%Y%m%d-%H%M%S"));
+                match taken(&sibling(stamp.clone())) {
+                    true => numbered_sibling(&stamp, sibling, taken),
+                    false => sibling(stamp),
+                }
+            }
+            ExistingPolicy::Number if taken(&folder) => numbered_sibling("", sibling, taken),
+            _ => folder,
+        };
+        self.staging = Some(Staging::new(
+            self.options.output.clone(),
+            self.get_folder_zip_path(&self.options.output),
+            self.options.existing_policy == ExistingPolicy::Append,
+        )?);
+        Ok(())
+    }
+
+    /// Folder the files are written to, the staging folder during a run.
+    fn folder(&self) -> &Path {
+        match &self.staging {
+            Some(staging) => &staging.folder,
+            None => &self.options.output,
+        }
+    }
+
+    /// Path of the archive of the whole output folder, `None` if not requested.
+    fn get_folder_zip_path(&self, folder: &Path) -> Option<PathBuf> {
+        match self.options.archive_mode {
+            ArchiveMode::Alongside | ArchiveMode::Instead => {
+                let folder_name = folder.file_name()?.to_string_lossy();
+                Some(folder.with_file_name(format!("{}.zip", folder_name)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Packs the output folder into `<folder>.zip`, the folder is dropped on commit for
+    /// [`ArchiveMode::Instead`].
+    fn write_folder_zip(&mut self) -> Result<(), Box<dyn Error>> {
+        let path = match self.staging.as_ref().and_then(Staging::archive_path) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        self.write_to_running_view(format!("Writing {}.zip", self.options.output.display()))?;
+        let mut files: Vec<PathBuf> = std::fs::read_dir(self.folder())?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        files.sort();
+        let folder_name = self.options.output.file_name().unwrap().to_string_lossy();
+        write_zip(
+            &path,
+            Some(&folder_name),
+            &files,
+            self.options.archive_level,
+        )?;
+        Ok(())
+    }
+
+    /// Replaces the files of a category by `<category>.zip`.
+    fn write_category_zip(
+        &mut self,
+        path: PathBuf,
+        files: &[OutputFile],
+    ) -> Result<(), Box<dyn Error>> {
+        let files: Vec<PathBuf> = files.iter().map(|file| file.path.clone()).collect();
+        write_zip(&path, None, &files, self.options.archive_level)?;
+        for file in files {
+            std::fs::remove_file(file)?;
         }
+        Ok(())
     }
 
     fn write_xlsx(
         &mut self,
         path_xlsx: PathBuf,
         records: &[StringRecord],
+        schema: &Schema,
+        table_name: &str,
         excel_wl: &mut i32,
     ) -> Result<(), Box<dyn Error>> {
-        let workbook = Workbook::new(path_xlsx.to_str().unwrap());
-        let date_format = Some(workbook.add_format().set_num_format("dd.mm.yyyy hh:mm:ss"));
-        match workbook.add_worksheet(None) {
-            Ok(mut worksheet) => {
-                for (row, record) in records.iter().enumerate() {
-                    *excel_wl += 1;
-                    self.write_to_running_view(format!("Excel lines added: {}", excel_wl));
-
-                    for (col, field) in record.iter().enumerate() {
-                        match try_parse_time(field) {
-                            Ok(datetime) => worksheet.write_datetime(
-                                row as u32,
-                                col as u16,
-                                &datetime,
-                                date_format.as_ref(),
-                            )?,
-                            Err(_) => {
-                                worksheet.write_string(row as u32, col as u16, field, None)?
-                            }
-                        };
-                    }
+        let mut workbook = Workbook::new();
+        let date_format = Format::new().set_num_format(&self.options.excel_date_format);
+        let datetime_format = Format::new().set_num_format(&self.options.excel_datetime_format);
+        let number_formats: Vec<_> = schema
+            .column_types
+            .iter()
+            .map(|column_type| {
+                column_type
+                    .num_format()
+                    .map(|num_format| Format::new().set_num_format(num_format))
+            })
+            .collect();
+        let quote_prefix = Format::new().set_quote_prefix();
+        let escape = self.options.xlsx_formula_policy == FormulaPolicy::Escape;
+        let file_name = file_name(&path_xlsx);
+        let worksheet = workbook.add_worksheet();
+        for (row, record) in records.iter().enumerate() {
+            *excel_wl += 1;
+            self.write_to_running_view(format!("Excel lines added: {}", excel_wl))?;
+
+            let row = row as u32;
+            for (col, field) in record.iter().enumerate() {
+                let col = col as u16;
+                if row == 0 {
+                    worksheet.write_string(row, col, field)?;
+                    continue;
                 }
-                *excel_wl -= 1; // account for header
-                workbook.close()?;
-                Ok(())
+                match (
+                    schema.value(col as usize, field),
+                    &number_formats[col as usize],
+                ) {
+                    (Value::Date(parsed), _) => match parsed.to_excel() {
+                        Ok(datetime) => {
+                            let format = match parsed {
+                                ParsedTime::Date(_) => &date_format,
+                                ParsedTime::DateTime(_) => &datetime_format,
+                            };
+                            worksheet.write_datetime_with_format(row, col, &datetime, format)?
+                        }
+                        Err(_) => worksheet.write_string(row, col, field)?,
+                    },
+                    (Value::Number(number), Some(format)) => {
+                        worksheet.write_number_with_format(row, col, number, format)?
+                    }
+                    (Value::Number(number), None) => worksheet.write_number(row, col, number)?,
+                    (Value::Boolean(value), _) => worksheet.write_boolean(row, col, value)?,
+                    (Value::Text(text), _) if escape && is_formula(text) => {
+                        self.formula_cells.push(FormulaCell {
+                            file: file_name.clone(),
+                            row: row as usize,
+                            column: self.headers[col as usize].to_string(),
+                        });
+                        worksheet.write_string_with_format(row, col, text, &quote_prefix)?
+                    }
+                    (Value::Text(text), _) => worksheet.write_string(row, col, text)?,
+                };
             }
-            Err(error) => Err(Box::new(error)),
         }
+
+        if let Some(style) = self.options.get_excel_table_style() {
+            let columns: Vec<TableColumn> = unique_headers(&self.headers)
+                .into_iter()
+                .map(|header| TableColumn::new().set_header(header))
+                .collect();
+            let table = Table::new()
+                .set_name(table_name)
+                .set_style(style)
+                .set_columns(&columns);
+            worksheet.add_table(
+                0,
+                0,
+                records.len() as u32 - 1,
+                self.headers.len() as u16 - 1,
+                &table,
+            )?;
+        }
+
+        *excel_wl -= 1; // account for header
+        workbook.save(path_xlsx)?;
+        Ok(())
     }
 
     fn write_csv(
@@ -135,96 +914,2323 @@ impl Transformer {
         records: &[StringRecord],
         csv_wl: &mut i32,
     ) -> Result<(), Box<dyn Error>> {
-        let mut wtr = WriterBuilder::new().delimiter(b';').from_path(path_csv)?;
-        for record in records.iter() {
+        let append = self.is_append(&path_csv);
+        if append {
+            self.check_csv_header(&path_csv)?;
+        }
+        let escape = self.options.csv_formula_policy == FormulaPolicy::Escape;
+        let file_name = file_name(&path_csv);
+        let mut wtr = self.csv_writer(&path_csv, append)?;
+        for (row, record) in records.iter().enumerate().skip(append as usize) {
             *csv_wl += 1;
-            self.write_to_running_view(format!("CSV lines added: {}", csv_wl));
-            wtr.write_record(record)?;
+            self.write_to_running_view(format!("CSV lines added: {}", csv_wl))?;
+            match escape && row > 0 {
+                true => wtr.write_record(&escape_formulas(
+                    record,
+                    &self.headers,
+                    &file_name,
+                    row,
+                    &mut self.formula_cells,
+                ))?,
+                false => wtr.write_record(record)?,
+            }
+        }
+        wtr.flush()?;
+        if !append {
+            *csv_wl -= 1; // account for header
         }
-        *csv_wl -= 1;
         Ok(())
     }
 
-    fn read_csv(&mut self, mut rdr: csv::Reader<std::fs::File>) -> CsvReadResult {
-        let mut categories: HashMap<String, (Vec<StringRecord>, String)> = HashMap::new();
-        let (mut csv_rl, mut cat_total) = (0, 0);
-        let category_idx = rdr.get_field(&self.options.selected_category)?;
-        let mut filter_option = None;
-        if let Some((filter_field, filter_value)) = self.options.filter.clone() {
-            filter_option = Some((rdr.get_field(&filter_field)?, filter_value));
+    /// CSV writer with the selected dialect, the BOM is only written to new files.
+    fn csv_writer(&self, path: &Path, append: bool) -> Result<csv::Writer<File>, Box<dyn Error>> {
+        let dialect = &self.options.csv_dialect;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)?;
+        if dialect.bom && !append {
+            file.write_all(UTF8_BOM)?;
         }
-        for record in rdr.records() {
-            let record = record?;
+        Ok(WriterBuilder::new()
+            .delimiter(dialect.get_delimiter())
+            .quote_style(dialect.get_quote_style())
+            .terminator(dialect.get_line_ending())
+            .from_writer(file))
+    }
 
-            if let Some((field_idx, filter_name)) = &filter_option {
-                let value = record.get(*field_idx).unwrap();
-                if value != filter_name {
-                    continue;
-                }
-            };
+    /// Writes the removed duplicates with all input columns, replacing earlier ones.
+    ///
+    /// Masks and the CSV formula policy apply here as well, the file goes out with the
+    /// category files.
+    fn write_duplicates(
+        &mut self,
+        path: &Path,
+        records: &[StringRecord],
+    ) -> Result<(), Box<dyn Error>> {
+        self.write_to_running_view(format!("Writing {}", DUPLICATES_FILE))?;
+        let masker = self.masker(&records[0])?;
+        let escape = self.options.csv_formula_policy == FormulaPolicy::Escape;
+        let mut wtr = self.csv_writer(path, false)?;
+        wtr.write_record(&records[0])?;
+        for (row, record) in records.iter().enumerate().skip(1) {
+            let record = masker.mask(record);
+            match escape {
+                true => wtr.write_record(&escape_formulas(
+                    &record,
+                    &records[0],
+                    DUPLICATES_FILE,
+                    row,
+                    &mut self.formula_cells,
+                ))?,
+                false => wtr.write_record(&record)?,
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
 
-            csv_rl += 1;
-            self.write_to_running_view(format!("CSV lines read {}", csv_rl));
+    /// Writes one row per category and statistics column, numbers in English notation.
+    fn write_statistics_csv(
+        &mut self,
+        path: &Path,
+        statistics: &[(String, Vec<(String, ColumnStats)>)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.write_to_running_view(format!("Writing {}", STATISTICS_CSV))?;
+        let headers: StringRecord = STATISTICS_HEADERS.iter().collect();
+        let escape = self.options.csv_formula_policy == FormulaPolicy::Escape;
+        let mut wtr = self.csv_writer(path, false)?;
+        wtr.write_record(&headers)?;
+        let rows = statistics.iter().flat_map(|(category, columns)| {
+            columns
+                .iter()
+                .map(move |(column, stats)| (category, column, stats))
+        });
+        for (row, (category, column, stats)) in rows.enumerate() {
+            let record: StringRecord = [
+                category.clone(),
+                column.clone(),
+                stats.count.to_string(),
+                stats.sum().map(format_number).unwrap_or_default(),
+                stats.average().map(format_number).unwrap_or_default(),
+                stats.min().map(format_value).unwrap_or_default(),
+                stats.max().map(format_value).unwrap_or_default(),
+                stats.distinct().to_string(),
+            ]
+            .iter()
+            .collect();
+            match escape {
+                true => wtr.write_record(&escape_formulas(
+                    &record,
+                    &headers,
+                    STATISTICS_CSV,
+                    row + 1,
+                    &mut self.formula_cells,
+                ))?,
+                false => wtr.write_record(&record)?,
+            }
+        }
+        wtr.flush()?;
+        Ok(())
+    }
 
-            if let Some(cat_field) = record.get(category_idx) {
-                let cat_field_key = cat_field.to_string().to_lowercase();
-                if !categories.contains_key(&cat_field.to_lowercase()) {
-                    cat_total += 1;
-                    categories
-                        .entry(cat_field_key.clone())
-                        .or_insert((vec![self.headers.clone()], cat_field.to_string()));
+    /// Writes the statistics like [`Transformer::write_statistics_csv`] with typed cells.
+    fn write_statistics_xlsx(
+        &mut self,
+        path: &Path,
+        statistics: &[(String, Vec<(String, ColumnStats)>)],
+    ) -> Result<(), Box<dyn Error>> {
+        self.write_to_running_view(format!("Writing {}", STATISTICS_XLSX))?;
+        let mut workbook = Workbook::new();
+        let date_format = Format::new().set_num_format(&self.options.excel_date_format);
+        let datetime_format = Format::new().set_num_format(&self.options.excel_datetime_format);
+        let quote_prefix = Format::new().set_quote_prefix();
+        let escape = self.options.xlsx_formula_policy == FormulaPolicy::Escape;
+        let worksheet = workbook.add_worksheet();
+        for (col, header) in STATISTICS_HEADERS.iter().enumerate() {
+            worksheet.write_string(0, col as u16, *header)?;
+        }
+        let rows = statistics.iter().flat_map(|(category, columns)| {
+            columns
+                .iter()
+                .map(move |(column, stats)| (category, column, stats))
+        });
+        for (row, (category, column, stats)) in rows.enumerate() {
+            let row = row as u32 + 1;
+            for (col, text) in [category.as_str(), column.as_str()]
+                .iter()
+                .copied()
+                .enumerate()
+            {
+                let col = col as u16;
+                match escape && is_formula(text) {
+                    true => {
+                        self.formula_cells.push(FormulaCell {
+                            file: STATISTICS_XLSX.to_string(),
+                            row: row as usize,
+                            column: STATISTICS_HEADERS[col as usize].to_string(),
+                        });
+                        worksheet.write_string_with_format(row, col, text, &quote_prefix)?
+                    }
+                    false => worksheet.write_string(row, col, text)?,
+                };
+            }
+            worksheet.write_number(row, 2, stats.count as f64)?;
+            if let (Some(sum), Some(average)) = (stats.sum(), stats.average()) {
+                worksheet.write_number(row, 3, sum)?;
+                worksheet.write_number(row, 4, average)?;
+            }
+            for (col, value) in [(5, stats.min()), (6, stats.max())] {
+                match value {
+                    Some(Value::Number(number)) => {
+                        worksheet.write_number(row, col, number)?;
+                    }
+                    Some(Value::Date(parsed)) => match parsed.to_excel() {
+                        Ok(datetime) => {
+                            let format = match parsed {
+                                ParsedTime::Date(_) => &date_format,
+                                ParsedTime::DateTime(_) => &datetime_format,
+                            };
+                            worksheet.write_datetime_with_format(row, col, &datetime, format)?;
+                        }
+                        Err(_) => {
+                            worksheet.write_string(row, col, parsed.to_iso())?;
+                        }
+                    },
+                    _ => {}
                 }
-                categories.get_mut(&cat_field_key).unwrap().0.push(record);
             }
+            worksheet.write_number(row, 7, stats.distinct() as f64)?;
         }
-        Ok((csv_rl, cat_total, categories))
+        worksheet.autofit();
+        workbook.save(path)?;
+        Ok(())
     }
 
-    /// Creates the file paths for csv and xlsx.
-    fn get_csv_xlsx_path(&mut self, mut category_sub_collection: String) -> (PathBuf, PathBuf) {
-        let (mut path_csv, mut path_xlsx) =
-            (self.options.output.clone(), self.options.output.clone());
-        if category_sub_collection.is_empty() {
-            category_sub_collection = format!("Empty_{}", self.options.selected_category)
+    /// Writes the crosstab with a total per category and value, headers and totals in bold.
+    fn write_crosstab(&mut self, path: &Path, crosstab: &Crosstab) -> Result<(), Box<dyn Error>> {
+        self.write_to_running_view(format!("Writing {}", CROSSTAB_FILE))?;
+        let mut workbook = Workbook::new();
+        let (bold, plain) = (Format::new().set_bold(), Format::new());
+        let escape = self.options.xlsx_formula_policy == FormulaPolicy::Escape;
+        let worksheet = workbook.add_worksheet();
+
+        let crosstab_column = self.options.crosstab_column.clone().unwrap_or_default();
+        let mut title = format!(
+            "{} \\ {}",
+            self.options
+                .get_header_name(&self.options.selected_category),
+            self.options.get_header_name(&crosstab_column)
+        );
+        if let Some(sum_column) = self.options.crosstab_sum.as_ref() {
+            title.push_str(&format!(
+                " (sum of {})",
+                self.options.get_header_name(sum_column)
+            ));
+        }
+        worksheet.write_string_with_format(0, 0, title, &bold)?;
+        let last_col = crosstab.values.len() as u16 + 1;
+        let last_row = crosstab.rows.len() as u32 + 1;
+
+        // values of the crosstab column as header, categories in the first column
+        let texts = crosstab
+            .values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| (0, idx as u16 + 1, value, &bold))
+            .chain(
+                crosstab
+                    .rows
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, (category, _))| (idx as u32 + 1, 0, category, &plain)),
+            );
+        for (row, col, text, format) in texts {
+            match escape && is_formula(text) {
+                true => {
+                    self.formula_cells.push(FormulaCell {
+                        file: CROSSTAB_FILE.to_string(),
+                        row: row as usize,
+                        column: match col {
+                            0 => "Category".to_string(),
+                            _ => crosstab_column.clone(),
+                        },
+                    });
+                    let format = format.clone().set_quote_prefix();
+                    worksheet.write_string_with_format(row, col, text, &format)?
+                }
+                false => worksheet.write_string_with_format(row, col, text, format)?,
+            };
+        }
+
+        for (idx, (_, cells)) in crosstab.rows.iter().enumerate() {
+            let row = idx as u32 + 1;
+            for (col, cell) in cells.iter().enumerate() {
+                worksheet.write_number(row, col as u16 + 1, *cell)?;
+            }
+            worksheet.write_number_with_format(row, last_col, cells.iter().sum::<f64>(), &bold)?;
         }
-        let valid_cat_name = replace_all_invalid_characters(&category_sub_collection);
-        path_csv.push(valid_cat_name.clone() + ".csv");
-        path_xlsx.push(valid_cat_name + ".xlsx");
-        (path_csv, path_xlsx)
+        worksheet.write_string_with_format(0, last_col, "Total", &bold)?;
+        worksheet.write_string_with_format(last_row, 0, "Total", &bold)?;
+        let totals = crosstab.column_totals();
+        for (col, total) in totals.iter().enumerate() {
+            worksheet.write_number_with_format(last_row, col as u16 + 1, *total, &bold)?;
+        }
+        worksheet.write_number_with_format(
+            last_row,
+            last_col,
+            totals.iter().sum::<f64>(),
+            &bold,
+        )?;
+        worksheet.set_freeze_panes(1, 1)?;
+        worksheet.autofit();
+        workbook.save(path)?;
+        Ok(())
     }
 
-    pub fn get_input_output_path(&self) -> Option<(String, String)> {
-        let input = self.options.input.to_str().unwrap().to_string();
-        let output = self.options.output.to_str().unwrap().to_string();
-        Some((input, output))
+    /// Whether rows are appended to an existing file, see [`ExistingPolicy::Append`].
+    fn is_append(&self, path: &Path) -> bool {
+        self.options.existing_policy == ExistingPolicy::Append && path.exists()
     }
-}
 
-impl Options {
-    pub fn new(
-        selected_category: String,
-        input: PathBuf,
-        output: PathBuf,
-        filter: Option<(String, String)>,
-    ) -> Options {
-        Options {
-            selected_category,
-            input,
-            output,
-            filter,
+    /// Compares the header of an existing CSV file with the input headers.
+    fn check_csv_header(&self, path_csv: &Path) -> Result<(), Box<dyn Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(self.options.csv_dialect.get_delimiter())
+            .has_headers(false)
+            .from_path(path_csv)?;
+        let mut header = StringRecord::new();
+        rdr.read_record(&mut header)?;
+        let header: Vec<&str> = header
+            .iter()
+            .map(|field| field.trim_start_matches('\u{feff}'))
+            .collect();
+        match header == self.headers.iter().collect::<Vec<_>>() {
+            true => Ok(()),
+            false => Err(Box::new(HeaderMismatchError)),
         }
     }
 
-    pub fn set_filter(&mut self, filter: Option<(String, String)>) -> Self {
-        self.filter = filter;
-        self.to_owned()
+    /// Writes the records as JSON array or newline delimited JSON, keyed by header.
+    ///
+    /// Values are typed only if type inference is enabled, dates become ISO 8601 strings.
+    fn write_json(
+        &mut self,
+        path_json: PathBuf,
+        records: &[StringRecord],
+        schema: &Schema,
+        format: OutputFormat,
+        json_wl: &mut i32,
+    ) -> Result<(), Box<dyn Error>> {
+        let keys = unique_headers(&self.headers);
+        let existing = match self.is_append(&path_json) {
+            true => read_json_objects(&path_json, &keys)?,
+            false => vec![],
+        };
+        let mut wtr = BufWriter::new(File::create(path_json)?);
+        if format == OutputFormat::Json {
+            wtr.write_all(b"[")?;
+        }
+        let written = existing.len();
+        let infer_types = self.options.infer_types;
+        let objects = records.iter().skip(1).map(|record| {
+            keys.iter()
+                .zip(record.iter())
+                .enumerate()
+                .map(|(col, (key, field))| {
+                    let value = match infer_types {
+                        true => json_value(schema.value(col, field), schema.column_type(col)),
+                        false => JsonValue::from(field),
+                    };
+                    (key.clone(), value)
+                })
+                .collect::<Map<String, JsonValue>>()
+        });
+        for (idx, object) in existing.into_iter().chain(objects).enumerate() {
+            if idx >= written {
+                *json_wl += 1;
+                self.write_to_running_view(format!("JSON lines added: {}", json_wl))?;
+            }
+
+            match format {
+                OutputFormat::Json if idx > 0 => wtr.write_all(b",\n")?,
+                OutputFormat::Json => wtr.write_all(b"\n")?,
+                _ => (),
+            }
+            serde_json::to_writer(&mut wtr, &object)?;
+            if format == OutputFormat::Ndjson {
+                wtr.write_all(b"\n")?;
+            }
+        }
+        if format == OutputFormat::Json {
+            wtr.write_all(b"\n]\n")?;
+        }
+        wtr.flush()?;
+        Ok(())
     }
 
-    pub fn get_selected_category(&self) -> String {
-        self.selected_category.clone()
+    /// Writes the records as Parquet or Arrow IPC file with the inferred column types.
+    fn write_columnar(
+        &mut self,
+        path: PathBuf,
+        records: &[StringRecord],
+        schema: &Schema,
+        format: OutputFormat,
+        columnar_wl: &mut i32,
+    ) -> Result<(), Box<dyn Error>> {
+        let arrow_schema = arrow_schema(&self.headers, schema);
+        let batch = record_batch(arrow_schema.clone(), &records[1..], schema)?;
+        let file = File::create(path)?;
+        match format {
+            OutputFormat::Parquet => {
+                let properties = WriterProperties::builder()
+                    .set_compression(parquet_compression(&self.options.parquet_compression))
+                    .set_max_row_group_size(self.options.parquet_row_group_size)
+                    .build();
+                let mut wtr = ArrowWriter::try_new(file, arrow_schema, Some(properties))?;
+                wtr.write(&batch)?;
+                wtr.close()?;
+            }
+            _ => {
+                let mut wtr = FileWriter::try_new(file, &arrow_schema)?;
+                wtr.write(&batch)?;
+                wtr.finish()?;
+            }
+        }
+        *columnar_wl += batch.num_rows() as i32;
+        self.write_to_running_view(format!("{} lines added: {}", format.label(), columnar_wl))?;
+        Ok(())
     }
 
-    pub fn get_filter(&self) -> Option<(String, String)> {
-        self.filter.clone()
+    /// Writes all categories into one SQLite database.
+    ///
+    /// Column types come from type inference, dates are stored as ISO 8601 text.
+    /// The table `run_metadata` describes the source and options of the run.
+    fn write_sqlite(
+        &mut self,
+        path_sqlite: PathBuf,
+        categories: &Categories,
+        schema: &Schema,
+        sqlite_wl: &mut i32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut connection = Connection::open(path_sqlite)?;
+        let transaction = connection.transaction()?;
+
+        let headers = unique_headers(&self.headers);
+        let mut columns: Vec<String> = headers
+            .iter()
+            .enumerate()
+            .map(|(col, name)| {
+                format!(
+                    "{} {}",
+                    quote_identifier(name),
+                    sql_type(schema.column_type(col))
+                )
+            })
+            .collect();
+        // A single table needs the category column, even if it is dropped from the files.
+        let add_category = self.options.sqlite_layout == SqliteLayout::SingleTable
+            && self.category_column.is_none()
+            && !self.options.selected_category.is_empty();
+        if add_category {
+            let name = self
+                .options
+                .get_header_name(&self.options.selected_category);
+            columns.insert(0, format!("{} TEXT", quote_identifier(&name)));
+        }
+        let placeholders = vec!["?"; columns.len()].join(", ");
+
+        let mut tables: Vec<(String, &str, &[StringRecord])> = vec![];
+        match self.options.sqlite_layout {
+            SqliteLayout::TablePerCategory => {
+                // sorted like the files, so the numbering doesn't depend on the hash order
+                let mut sorted: Vec<_> = categories.values().collect();
+                sorted.sort_by(|(_, a), (_, b)| a.cmp(b));
+                let mut taken = HashSet::new();
+                taken.insert("run_metadata".to_string());
+                for (records, category_sub_collection) in sorted {
+                    tables.push((
+                        self.sqlite_table_name(category_sub_collection, &mut taken),
+                        category_sub_collection,
+                        records,
+                    ));
+                }
+            }
+            SqliteLayout::SingleTable => {
+                let category_column = match self.category_column {
+                    Some(idx) => headers[idx].clone(),
+                    None => self
+                        .options
+                        .get_header_name(&self.options.selected_category),
+                };
+                transaction.execute_batch(&format!(
+                    "CREATE TABLE {} ({});",
+                    SQLITE_RECORDS_TABLE,
+                    columns.join(", "),
+                ))?;
+                if !category_column.is_empty() {
+                    transaction.execute_batch(&format!(
+                        "CREATE INDEX {} ON {} ({});",
+                        quote_identifier(&format!("{}_category", SQLITE_RECORDS_TABLE)),
+                        SQLITE_RECORDS_TABLE,
+                        quote_identifier(&category_column),
+                    ))?;
+                }
+                for (records, category_sub_collection) in categories.values() {
+                    tables.push((
+                        SQLITE_RECORDS_TABLE.to_string(),
+                        category_sub_collection,
+                        records,
+                    ));
+                }
+            }
+        }
+
+        for (table, category_sub_collection, records) in tables {
+            if self.options.sqlite_layout == SqliteLayout::TablePerCategory {
+                transaction.execute_batch(&format!(
+                    "CREATE TABLE {} ({});",
+                    quote_identifier(&table),
+                    columns.join(", ")
+                ))?;
+            }
+            let mut statement = transaction.prepare(&format!(
+                "INSERT INTO {} VALUES ({})",
+                quote_identifier(&table),
+                placeholders
+            ))?;
+            for record in records.iter().skip(1) {
+                *sqlite_wl += 1;
+                self.write_to_running_view(format!("SQLite rows added: {}", sqlite_wl))?;
+
+                let category = match add_category {
+                    true => Some(SqlValue::Text(category_sub_collection.to_string())),
+                    false => None,
+                };
+                let values =
+                    category
+                        .into_iter()
+                        .chain(record.iter().enumerate().map(|(col, field)| {
+                            match self.options.infer_types {
+                                true => {
+                                    sql_value(schema.value(col, field), schema.column_type(col))
+                                }
+                                false => SqlValue::Text(field.to_string()),
+                            }
+                        }));
+                statement.execute(params_from_iter(values))?;
+            }
+        }
+
+        transaction.execute_batch(
+            "CREATE TABLE run_metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);",
+        )?;
+        let filter = match &self.options.filter {
+            Some((filter_field, filter_value)) => format!("{} = {}", filter_field, filter_value),
+            None => String::new(),
+        };
+        let metadata = [
+            ("source", self.options.input.to_string_lossy().to_string()),
+            ("category", self.options.selected_category.clone()),
+            ("filter", filter),
+            ("created", self.started.to_rfc3339()),
+            ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ];
+        for (key, value) in metadata.iter() {
+            transaction.execute(
+                "INSERT INTO run_metadata (key, value) VALUES (?1, ?2)",
+                params![key, value],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Table name of a category. Names starting with `sqlite_` are reserved by SQLite.
+    ///
+    /// SQLite compares table names ignoring case, if a name is in `taken` a number is
+    /// appended (`Nord`, `Nord_2`). The name is added to `taken`.
+    fn sqlite_table_name(
+        &self,
+        category_sub_collection: &str,
+        taken: &mut HashSet<String>,
+    ) -> String {
+        let name = match category_sub_collection.is_empty() {
+            true => format!("Empty_{}", self.options.selected_category),
+            false => category_sub_collection.to_string(),
+        };
+        let name = match name.to_lowercase().starts_with("sqlite_") {
+            true => format!("t_{}", name),
+            false => name,
+        };
+        let name = (1..)
+            .map(|n| match n {
+                1 => name.clone(),
+                n => format!("{}_{}", name, n),
+            })
+            .find(|name| !taken.contains(&name.to_lowercase()))
+            .unwrap();
+        taken.insert(name.to_lowercase());
+        name
+    }
+
+    fn read_csv(&mut self, mut rdr: csv::Reader<std::fs::File>) -> CsvReadResult {
+        let mut categories: Categories = HashMap::new();
+        let (mut csv_rl, mut cat_total) = (0, 0);
+        let steps = column_steps(&self.options.cleaning_rules, rdr.headers()?)
+            .map_err(|_| CleaningError)?;
+        let cleaning = steps.iter().any(|steps| !steps.is_empty());
+        let mut lookup = self.lookup(rdr.headers()?)?;
+        let mut joined_headers = rdr.headers()?.clone();
+        if let Some(lookup) = lookup.as_ref() {
+            joined_headers.extend(lookup.headers.iter());
+        }
+        let (mut input_headers, expressions) =
+            parse_derived_columns(&joined_headers, &self.options.derived_columns)
+                .map_err(|_| ExpressionError)?;
+        let date_patterns: Vec<Vec<String>> = input_headers
+            .iter()
+            .map(|header| self.options.get_date_patterns(header))
+            .collect();
+        let general_patterns = self.options.get_general_date_patterns();
+        let category_idx = match self.options.selected_category.is_empty() {
+            true => None,
+            false => Some(input_headers.get_field(&self.options.selected_category)?),
+        };
+        // without category all rows go to one file named like the input
+        let input_stem = self.get_input_stem();
+        let mut filter_option = None;
+        if let Some((filter_field, filter_value)) = self.options.filter.clone() {
+            filter_option = Some((input_headers.get_field(&filter_field)?, filter_value));
+        }
+        let mut dedup = self.deduplicator(&input_headers)?;
+        // replaced rows leave the statistics and go to the duplicates file with all input
+        // columns, so they are projected after reading
+        let keep_input = dedup.is_some() && self.options.dedup_keep_last;
+        self.headers = input_headers;
+        let columns =
+            self.column_indices(category_idx, filter_option.as_ref().map(|(idx, _)| *idx))?;
+        self.category_column = columns.iter().position(|&idx| Some(idx) == category_idx);
+        self.source_headers = project(&self.headers, &columns);
+        self.headers = self
+            .source_headers
+            .iter()
+            .map(|header| self.options.get_header_name(header))
+            .collect();
+        let mut statistics = self.statistics(&columns)?;
+        for record in rdr.records() {
+            let mut record = record?;
+            if cleaning {
+                record = clean_record(&record, &steps);
+            }
+            let matched = lookup
+                .as_ref()
+                .is_none_or(|lookup| lookup.join(&mut record));
+            for expression in expressions.iter() {
+                let value = expression.evaluate(&record, &date_patterns, &general_patterns);
+                record.push_field(&value);
+            }
+
+            if let Some((field_idx, filter_name)) = &filter_option {
+                let value = record.get(*field_idx).unwrap();
+                if value != filter_name {
+                    continue;
+                }
+            };
+            if let (false, Some(lookup)) = (matched, lookup.as_mut()) {
+                lookup.add_unmatched(&record);
+                if self.options.lookup_join == JoinKind::Inner {
+                    continue;
+                }
+            }
+
+            csv_rl += 1;
+            self.write_to_running_view(format!("CSV lines read {}", csv_rl))?;
+
+            let cat_field = match category_idx {
+                Some(category_idx) => record.get(category_idx),
+                None => Some(input_stem.as_str()),
+            };
+            if let Some(cat_field) = cat_field {
+                let cat_field_key = cat_field.to_string().to_lowercase();
+                if !categories.contains_key(&cat_field.to_lowercase()) {
+                    cat_total += 1;
+                    categories
+                        .entry(cat_field_key.clone())
+                        .or_insert((vec![self.headers.clone()], cat_field.to_string()));
+                }
+                let records = &mut categories.get_mut(&cat_field_key).unwrap().0;
+                if dedup
+                    .as_mut()
+                    .is_none_or(|dedup| dedup.insert(&record, &cat_field_key, records.len()))
+                {
+                    if let Some(statistics) = statistics.as_mut() {
+                        statistics.add(&cat_field_key, &record);
+                    }
+                    records.push(match keep_input {
+                        true => record,
+                        false => project(&record, &columns),
+                    });
+                }
+            }
+        }
+
+        if let Some(dedup) = dedup.as_mut() {
+            if self.options.dedup_keep_last {
+                for (cat_field_key, (records, _)) in categories.iter_mut() {
+                    let rows = records.split_off(1);
+                    for (position, record) in (1..).zip(rows) {
+                        if dedup.is_replaced(cat_field_key, position) {
+                            if let Some(statistics) = statistics.as_mut() {
+                                statistics.remove(cat_field_key, &record);
+                            }
+                            dedup.add_replaced(cat_field_key, position, record);
+                        } else if keep_input {
+                            records.push(project(&record, &columns));
+                        } else {
+                            records.push(record);
+                        }
+                    }
+                }
+            }
+            // all rows of a category can be duplicates of rows in other categories
+            categories.retain(|_, (records, _)| records.len() > 1);
+            cat_total = categories.len() as i32;
+        }
+        Ok((csv_rl, cat_total, categories, dedup, lookup, statistics))
+    }
+
+    /// Rows of every part of a category, all rows without parts.
+    ///
+    /// Bytes are measured in the written CSV, with header, BOM and neutralized formulas.
+    fn chunks(&self, records: &[StringRecord]) -> Vec<Range<usize>> {
+        match self.options.chunk_mode {
+            ChunkMode::Off => std::iter::once(1..records.len()).collect(),
+            ChunkMode::Rows => chunk_rows(records, self.options.chunk_size),
+            ChunkMode::Bytes => {
+                let dialect = &self.options.csv_dialect;
+                let escape = self.options.csv_formula_policy == FormulaPolicy::Escape;
+                let mut builder = WriterBuilder::new();
+                builder
+                    .delimiter(dialect.get_delimiter())
+                    .quote_style(dialect.get_quote_style())
+                    .terminator(dialect.get_line_ending());
+                // writing to memory doesn't fail
+                let size = |record: &StringRecord, escape: bool| {
+                    let mut wtr = builder.from_writer(vec![]);
+                    let _ = wtr.write_record(record);
+                    let escaped = match escape {
+                        true => record.iter().filter(|field| is_formula(field)).count(),
+                        false => 0,
+                    };
+                    wtr.into_inner().map_or(0, |bytes| bytes.len()) + escaped
+                };
+                let bom = match dialect.bom {
+                    true => UTF8_BOM.len(),
+                    false => 0,
+                };
+                let overhead = bom + size(&records[0], false);
+                chunk_bytes(records, self.options.chunk_size, overhead, |record| {
+                    size(record, escape)
+                })
+            }
+        }
+    }
+
+    /// Crosstab of [`Options::get_crosstab`] over the sorted categories, `None` without one.
+    fn crosstab(
+        &self,
+        categories: &Categories,
+        schema: &Schema,
+    ) -> Result<Option<Crosstab>, Box<dyn Error>> {
+        let column = match self.options.crosstab_column.as_ref() {
+            Some(column) => column,
+            None => return Ok(None),
+        };
+        let position =
+            |name: &str| match self.source_headers.iter().position(|header| header == name) {
+                Some(col) => Ok(col),
+                None => Err(Box::new(HeaderError) as Box<dyn Error>),
+            };
+        let column = position(column)?;
+        let sum_column = match self.options.crosstab_sum.as_ref() {
+            Some(sum_column) => {
+                let col = position(sum_column)?;
+                Some((col, self.typed_schema(categories, schema).column_type(col)))
+            }
+            None => None,
+        };
+        let mut sorted: Vec<_> = categories.values().collect();
+        sorted.sort_by(|(_, a), (_, b)| a.cmp(b));
+        Ok(Some(Crosstab::new(
+            sorted
+                .into_iter()
+                .map(|(records, category)| (category.as_str(), &records[1..])),
+            column,
+            sum_column,
+        )))
+    }
+
+    /// Statistics of the chosen output columns, `None` without statistics columns.
+    ///
+    /// `columns` holds the input position of every output column, see
+    /// [`Transformer::column_indices`]. Rows are added while the input is read.
+    fn statistics(&self, columns: &[usize]) -> Result<Option<Statistics>, Box<dyn Error>> {
+        if self.options.statistics_columns.is_empty() {
+            return Ok(None);
+        }
+        let mut stats_columns = vec![];
+        for name in self.options.statistics_columns.iter() {
+            match self.source_headers.iter().position(|header| header == name) {
+                Some(col) => stats_columns.push((columns[col], col)),
+                None => return Err(Box::new(HeaderError)),
+            }
+        }
+        Ok(Some(Statistics::new(stats_columns)))
+    }
+
+    /// Reference file of [`Options::get_lookup`], `None` without lookup.
+    fn lookup(&self, input_headers: &StringRecord) -> Result<Option<Lookup>, Box<dyn Error>> {
+        match self.options.lookup_file.as_ref() {
+            None => Ok(None),
+            Some(path) => {
+                let (input_key, reference_key) = &self.options.lookup_keys;
+                match Lookup::read(path, input_key, reference_key, input_headers) {
+                    Ok(lookup) => Ok(Some(lookup)),
+                    Err(_) => Err(Box::new(LookupError)),
+                }
+            }
+        }
+    }
+
+    /// Deduplicator for the settings, `None` if duplicates are kept.
+    fn deduplicator(
+        &self,
+        input_headers: &StringRecord,
+    ) -> Result<Option<Deduplicator>, Box<dyn Error>> {
+        if self.options.dedup_scope == DedupScope::Off {
+            return Ok(None);
+        }
+        let mut key_columns = vec![];
+        for name in self.options.dedup_keys.iter() {
+            match input_headers.iter().position(|header| header == name) {
+                Some(col) => key_columns.push(col),
+                None => return Err(Box::new(HeaderError)),
+            }
+        }
+        Ok(Some(Deduplicator::new(
+            key_columns,
+            self.options.dedup_scope == DedupScope::PerCategory,
+            self.options.dedup_keep_last,
+            Some(input_headers).filter(|_| self.options.write_duplicates),
+        )))
+    }
+
+    /// Sorts the rows of every category by [`Options::get_sort_keys`].
+    fn sort_categories(
+        &self,
+        categories: &mut Categories,
+        schema: &Schema,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.options.sort_keys.is_empty() {
+            return Ok(());
+        }
+        let mut keys = vec![];
+        for (name, descending) in self.options.sort_keys.iter() {
+            match self.source_headers.iter().position(|header| header == name) {
+                Some(col) => keys.push((col, *descending)),
+                None => return Err(Box::new(HeaderError)),
+            }
+        }
+        let schema = self.typed_schema(categories, schema).into_owned();
+        for (records, _) in categories.values_mut() {
+            sort_records(&mut records[1..], &keys, &schema);
+        }
+        Ok(())
+    }
+
+    /// Applies the masks of [`Options::get_masking`] to the output columns.
+    ///
+    /// Returns whether any column is masked.
+    fn mask_categories(&self, categories: &mut Categories) -> Result<bool, Box<dyn Error>> {
+        let masker = self.masker(&self.source_headers)?;
+        if masker.is_empty() {
+            return Ok(false);
+        }
+        for (records, _) in categories.values_mut() {
+            for record in records.iter_mut().skip(1) {
+                *record = masker.mask(record);
+            }
+        }
+        Ok(true)
+    }
+
+    fn masker(&self, headers: &StringRecord) -> Result<Masker, Box<dyn Error>> {
+        Masker::new(
+            &self.options.masks,
+            &self.options.mask_salt,
+            headers,
+            |column| self.options.get_date_patterns(column),
+        )
+        .map_err(|_| Box::new(MaskingError) as Box<dyn Error>)
+    }
+
+    /// Types of the output columns, all text without `infer_types`.
+    fn infer_schema(&self, categories: &Categories, infer_types: bool) -> Schema {
+        let date_patterns: Vec<Vec<String>> = self
+            .source_headers
+            .iter()
+            .map(|column| self.options.get_date_patterns(column))
+            .collect();
+        Schema {
+            column_types: match infer_types {
+                true => infer_column_types(
+                    &date_patterns,
+                    categories
+                        .values()
+                        .flat_map(|(records, _)| records.iter().skip(1)),
+                ),
+                false => vec![ColumnType::Text; self.headers.len()],
+            },
+            date_patterns,
+        }
+    }
+
+    /// Schema for sorting and reports, which compare and sum typed values.
+    ///
+    /// Without type inference for the output the types are inferred for these only.
+    fn typed_schema<'a>(&self, categories: &Categories, schema: &'a Schema) -> Cow<'a, Schema> {
+        match self.options.infer_types {
+            true => Cow::Borrowed(schema),
+            false => Cow::Owned(self.infer_schema(categories, true)),
+        }
+    }
+
+    /// Input columns written to the output, in output order.
+    ///
+    /// Selected names are matched exactly, repeated names take the next column of that name.
+    fn column_indices(
+        &self,
+        category_idx: Option<usize>,
+        filter_idx: Option<usize>,
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        let mut columns = vec![];
+        match self.options.columns.is_empty() {
+            true => columns.extend(0..self.headers.len()),
+            false => {
+                for name in self.options.columns.iter() {
+                    match self
+                        .headers
+                        .iter()
+                        .enumerate()
+                        .position(|(idx, header)| header == name && !columns.contains(&idx))
+                    {
+                        Some(idx) => columns.push(idx),
+                        None => return Err(Box::new(HeaderError)),
+                    }
+                }
+            }
+        }
+        if self.options.drop_category_columns {
+            columns.retain(|&idx| Some(idx) != category_idx && Some(idx) != filter_idx);
+        }
+        Ok(columns)
+    }
+
+    /// File names of the sorted categories without extension.
+    ///
+    /// Names are compared ignoring case like on Windows and macOS. If a name is taken by an
+    /// earlier category, the manifest or the database, a number is appended (`a_b`, `a_b_2`).
+    ///
+    /// With parts the names of all parts are checked, see [`Transformer::part_stems`].
+    fn get_file_stems(
+        &self,
+        categories: &[&(Vec<StringRecord>, String)],
+        parts: &[Vec<Range<usize>>],
+    ) -> Vec<String> {
+        let extensions: Vec<&str> = self
+            .options
+            .formats
+            .iter()
+            .filter(|format| **format != OutputFormat::Sqlite)
+            .map(|format| format.extension())
+            .collect();
+        let category_zip = self.options.archive_mode == ArchiveMode::PerCategory;
+
+        let mut taken: HashSet<String> = HashSet::new();
+        if self.options.manifest {
+            taken.insert(MANIFEST_FILE.to_lowercase());
+        }
+        if self.options.dedup_scope != DedupScope::Off && self.options.write_duplicates {
+            taken.insert(DUPLICATES_FILE.to_lowercase());
+        }
+        if self.options.crosstab_column.is_some() {
+            taken.insert(CROSSTAB_FILE.to_string());
+        }
+        if !self.options.statistics_columns.is_empty() {
+            taken.insert(STATISTICS_CSV.to_string());
+            taken.insert(STATISTICS_XLSX.to_string());
+        }
+        if self.options.formats.contains(&OutputFormat::Sqlite) {
+            let folder_name = self.options.output.file_name().unwrap().to_string_lossy();
+            taken.insert(format!("{}.sqlite", folder_name).to_lowercase());
+        }
+
+        categories
+            .iter()
+            .zip(parts)
+            .enumerate()
+            .map(|(idx, ((records, category_sub_collection), parts))| {
+                let file_stem = self.get_file_stem(category_sub_collection, records, idx + 1);
+                let file_names = |stem: &str| {
+                    let mut names: Vec<String> = self
+                        .part_stems(stem, parts.len())
+                        .iter()
+                        .flat_map(|part| {
+                            extensions
+                                .iter()
+                                .map(move |extension| format!("{}.{}", part, extension))
+                        })
+                        .collect();
+                    if category_zip {
+                        names.push(format!("{}.zip", stem));
+                    }
+                    names
+                        .into_iter()
+                        .map(|name| name.to_lowercase())
+                        .collect::<Vec<_>>()
+                };
+                // parts and extension of the longest file name, without the stem
+                let suffix_bytes =
+                    SIBLING_BYTES + file_names("").iter().map(String::len).max().unwrap_or(0);
+                let file_stem = (1..)
+                    .map(|n| {
+                        let number = match n {
+                            1 => String::new(),
+                            n => format!("_{}", n),
+                        };
+                        let stem = truncate_name(&file_stem, suffix_bytes + number.len());
+                        format!("{}{}", stem, number)
+                    })
+                    .find(|stem| file_names(stem).iter().all(|name| !taken.contains(name)))
+                    .unwrap();
+                taken.extend(file_names(&file_stem));
+                file_stem
+            })
+            .collect()
+    }
+
+    /// Stems of the parts of a category file, only the file stem without parts.
+    fn part_stems(&self, file_stem: &str, parts: usize) -> Vec<String> {
+        match self.options.chunk_mode {
+            ChunkMode::Off => vec![file_stem.to_string()],
+            _ => (1..=parts)
+                .map(|part| part_stem(file_stem, part, parts))
+                .collect(),
+        }
+    }
+
+    /// File name of a category without extension, named by the file template.
+    fn get_file_stem(
+        &self,
+        category_sub_collection: &str,
+        records: &[StringRecord],
+        index: usize,
+    ) -> String {
+        let category_sub_collection = match category_sub_collection.is_empty() {
+            true => format!("Empty_{}", self.options.selected_category),
+            false => category_sub_collection.to_string(),
+        };
+        let file_name = render(
+            &self.options.file_template,
+            &NameContext {
+                category: &category_sub_collection,
+                filter: self.options.filter.as_ref(),
+                input_stem: &self.get_input_stem(),
+                rowcount: records.len() - 1,
+                index: Some(index),
+                date: self.started,
+            },
+        );
+        replace_all_invalid_characters(&file_name)
+    }
+
+    fn get_input_stem(&self) -> String {
+        self.options
+            .input
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Creates the file path of a category for the given format.
+    fn get_output_path(&self, file_stem: &str, format: OutputFormat) -> PathBuf {
+        self.folder()
+            .join(format!("{}.{}", file_stem, format.extension()))
+    }
+
+    pub fn get_input_output_path(&self) -> Option<(String, String)> {
+        let input = self.options.input.to_str().unwrap().to_string();
+        let output = self.options.output.to_str().unwrap().to_string();
+        Some((input, output))
+    }
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: CSV_DELIMITERS[0].0.to_string(),
+            quote_style: CSV_QUOTE_STYLES[0].0.to_string(),
+            line_ending: CSV_LINE_ENDINGS[0].0.to_string(),
+            bom: false,
+        }
+    }
+}
+
+impl CsvDialect {
+    pub fn get_delimiter(&self) -> u8 {
+        lookup(&CSV_DELIMITERS, &self.delimiter)
+    }
+
+    pub fn get_quote_style(&self) -> QuoteStyle {
+        lookup(&CSV_QUOTE_STYLES, &self.quote_style)
+    }
+
+    pub fn get_line_ending(&self) -> Terminator {
+        lookup(&CSV_LINE_ENDINGS, &self.line_ending)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Prefixes values starting like a formula with `'`, which spreadsheets show as text.
+fn escape_formulas(
+    record: &StringRecord,
+    headers: &StringRecord,
+    file: &str,
+    row: usize,
+    formula_cells: &mut Vec<FormulaCell>,
+) -> StringRecord {
+    record
+        .iter()
+        .enumerate()
+        .map(|(col, field)| match is_formula(field) {
+            true => {
+                formula_cells.push(FormulaCell {
+                    file: file.to_string(),
+                    row,
+                    column: headers[col].to_string(),
+                });
+                format!("'{}", field)
+            }
+            false => field.to_string(),
+        })
+        .collect()
+}
+
+/// Fields of the given columns in that order.
+fn project(record: &StringRecord, columns: &[usize]) -> StringRecord {
+    columns
+        .iter()
+        .map(|&idx| record.get(idx).unwrap_or_default())
+        .collect()
+}
+
+fn json_value(value: Value, column_type: ColumnType) -> JsonValue {
+    match value {
+        Value::Number(number) if column_type == ColumnType::Integer => {
+            JsonValue::from(number as i64)
+        }
+        Value::Number(number) => JsonValue::from(number),
+        Value::Boolean(value) => JsonValue::from(value),
+        Value::Date(parsed) => JsonValue::from(parsed.to_iso()),
+        Value::Text(text) => JsonValue::from(text),
+    }
+}
+
+/// Objects of an existing JSON or NDJSON file, which must have the given keys.
+fn read_json_objects(
+    path_json: &Path,
+    keys: &[String],
+) -> Result<Vec<Map<String, JsonValue>>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path_json)?;
+    let objects: Vec<Map<String, JsonValue>> = match content.trim_start().starts_with('[') {
+        true => serde_json::from_str(&content)?,
+        false => content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?,
+    };
+    match objects.iter().all(|object| object.keys().eq(keys.iter())) {
+        true => Ok(objects),
+        false => Err(Box::new(HeaderMismatchError)),
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Decimal { .. } | ColumnType::Percentage { .. } => "REAL",
+        ColumnType::Text | ColumnType::Date | ColumnType::DateTime => "TEXT",
+    }
+}
+
+/// Empty fields of typed columns become NULL.
+fn sql_value(value: Value, column_type: ColumnType) -> SqlValue {
+    match value {
+        Value::Number(number) if column_type == ColumnType::Integer => {
+            SqlValue::Integer(number as i64)
+        }
+        Value::Number(number) => SqlValue::Real(number),
+        Value::Boolean(value) => SqlValue::Integer(value as i64),
+        Value::Date(parsed) => SqlValue::Text(parsed.to_iso()),
+        Value::Text(text) if text.is_empty() && column_type != ColumnType::Text => SqlValue::Null,
+        Value::Text(text) => SqlValue::Text(text.to_string()),
+    }
+}
+
+fn parquet_compression(label: &str) -> Compression {
+    match label {
+        "Zstd" => Compression::ZSTD(ZstdLevel::default()),
+        "Gzip" => Compression::GZIP(GzipLevel::default()),
+        "Uncompressed" => Compression::UNCOMPRESSED,
+        _ => Compression::SNAPPY,
+    }
+}
+
+/// First folder with the suffix `<suffix>_<n>` for which `taken` is false, starting at 2.
+fn numbered_sibling(
+    suffix: &str,
+    sibling: impl Fn(String) -> PathBuf,
+    taken: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    (2..)
+        .map(|n| sibling(format!("{}_{}", suffix, n)))
+        .find(|folder| !taken(folder))
+        .unwrap()
+}
+
+/// Value for a label, falls back to the first entry for unknown labels.
+fn lookup<T: Copy>(entries: &[(&str, T)], label: &str) -> T {
+    entries
+        .iter()
+        .find(|(name, _)| *name == label)
+        .unwrap_or(&entries[0])
+        .1
+}
+
+impl Options {
+    pub fn new(
+        selected_category: String,
+        input: PathBuf,
+        output: PathBuf,
+        filter: Option<(String, String)>,
+    ) -> Options {
+        Options {
+            selected_category,
+            input,
+            output,
+            filter,
+            infer_types: true,
+            date_patterns: DEFAULT_DATE_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            excel_date_format: EXCEL_DATE_FORMATS[0].to_string(),
+            excel_datetime_format: EXCEL_DATETIME_FORMATS[0].to_string(),
+            excel_table_style: None,
+            csv_dialect: CsvDialect::default(),
+            csv_formula_policy: FormulaPolicy::Escape,
+            xlsx_formula_policy: FormulaPolicy::Escape,
+            formats: vec![OutputFormat::Csv, OutputFormat::Xlsx],
+            sqlite_layout: SqliteLayout::TablePerCategory,
+            parquet_compression: PARQUET_COMPRESSIONS[0].to_string(),
+            parquet_row_group_size: PARQUET_ROW_GROUP_SIZE,
+            archive_mode: ArchiveMode::None,
+            archive_level: ARCHIVE_LEVEL,
+            existing_policy: ExistingPolicy::Fail,
+            columns: vec![],
+            drop_category_columns: false,
+            header_renames: vec![],
+            cleaning_rules: vec![],
+            lookup_file: None,
+            lookup_keys: (String::new(), String::new()),
+            lookup_join: JoinKind::Left,
+            derived_columns: vec![],
+            masks: vec![],
+            mask_salt: String::new(),
+            sort_keys: vec![],
+            statistics_columns: vec![],
+            crosstab_column: None,
+            crosstab_sum: None,
+            dedup_scope: DedupScope::Off,
+            dedup_keys: vec![],
+            dedup_keep_last: false,
+            write_duplicates: false,
+            folder_template: FOLDER_TEMPLATE.to_string(),
+            file_template: FILE_TEMPLATE.to_string(),
+            manifest: true,
+            chunk_mode: ChunkMode::Off,
+            chunk_size: CHUNK_ROWS,
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: Option<(String, String)>) -> Self {
+        self.filter = filter;
+        self.to_owned()
+    }
+
+    pub fn get_selected_category(&self) -> String {
+        self.selected_category.clone()
+    }
+
+    pub fn get_filter(&self) -> Option<(String, String)> {
+        self.filter.clone()
+    }
+
+    pub fn set_infer_types(&mut self, infer_types: bool) -> Self {
+        self.infer_types = infer_types;
+        self.to_owned()
+    }
+
+    pub fn get_infer_types(&self) -> bool {
+        self.infer_types
+    }
+
+    pub fn set_date_patterns(&mut self, date_patterns: Vec<String>) -> Self {
+        self.date_patterns = date_patterns;
+        self.to_owned()
+    }
+
+    pub fn get_date_pattern_lines(&self) -> Vec<String> {
+        self.date_patterns.clone()
+    }
+
+    /// Patterns for a column. Column specific patterns replace the general ones.
+    pub fn get_date_patterns(&self, column: &str) -> Vec<String> {
+        let (specific, general): (Vec<_>, Vec<_>) = self
+            .date_patterns
+            .iter()
+            .partition(|line| line.contains(" = "));
+        let specific: Vec<String> = specific
+            .iter()
+            .filter_map(|line| line.split_once(" = "))
+            .filter(|(name, _)| name.trim() == column)
+            .map(|(_, pattern)| pattern.trim().to_string())
+            .collect();
+        match specific.is_empty() {
+            true => general.into_iter().cloned().collect(),
+            false => specific,
+        }
+    }
+
+    /// Patterns which aren't restricted to a column.
+    pub fn get_general_date_patterns(&self) -> Vec<String> {
+        self.date_patterns
+            .iter()
+            .filter(|line| !line.contains(" = "))
+            .cloned()
+            .collect()
+    }
+
+    pub fn set_excel_date_formats(&mut self, date_format: String, datetime_format: String) -> Self {
+        self.excel_date_format = date_format;
+        self.excel_datetime_format = datetime_format;
+        self.to_owned()
+    }
+
+    pub fn get_excel_date_formats(&self) -> (String, String) {
+        (
+            self.excel_date_format.clone(),
+            self.excel_datetime_format.clone(),
+        )
+    }
+
+    pub fn set_excel_table_style(&mut self, excel_table_style: Option<String>) -> Self {
+        self.excel_table_style = excel_table_style;
+        self.to_owned()
+    }
+
+    pub fn get_excel_table_style_label(&self) -> Option<String> {
+        self.excel_table_style.clone()
+    }
+
+    pub fn get_excel_table_style(&self) -> Option<TableStyle> {
+        let label = self.excel_table_style.as_ref()?;
+        EXCEL_TABLE_STYLES
+            .iter()
+            .find(|(name, _)| name == label)
+            .map(|(_, style)| *style)
+    }
+
+    pub fn set_csv_dialect(&mut self, csv_dialect: CsvDialect) -> Self {
+        self.csv_dialect = csv_dialect;
+        self.to_owned()
+    }
+
+    pub fn get_csv_dialect(&self) -> CsvDialect {
+        self.csv_dialect.clone()
+    }
+
+    pub fn set_formula_policies(&mut self, csv: FormulaPolicy, xlsx: FormulaPolicy) -> Self {
+        self.csv_formula_policy = csv;
+        self.xlsx_formula_policy = xlsx;
+        self.to_owned()
+    }
+
+    /// Policies for CSV and Excel files.
+    pub fn get_formula_policies(&self) -> (FormulaPolicy, FormulaPolicy) {
+        (self.csv_formula_policy, self.xlsx_formula_policy)
+    }
+
+    pub fn set_formats(&mut self, formats: Vec<OutputFormat>) -> Self {
+        self.formats = formats;
+        self.to_owned()
+    }
+
+    pub fn get_formats(&self) -> Vec<OutputFormat> {
+        self.formats.clone()
+    }
+
+    pub fn set_sqlite_layout(&mut self, sqlite_layout: SqliteLayout) -> Self {
+        self.sqlite_layout = sqlite_layout;
+        self.to_owned()
+    }
+
+    pub fn get_sqlite_layout(&self) -> SqliteLayout {
+        self.sqlite_layout
+    }
+
+    pub fn set_parquet_settings(&mut self, compression: String, row_group_size: usize) -> Self {
+        self.parquet_compression = compression;
+        self.parquet_row_group_size = row_group_size;
+        self.to_owned()
+    }
+
+    pub fn get_parquet_settings(&self) -> (String, usize) {
+        (
+            self.parquet_compression.clone(),
+            self.parquet_row_group_size,
+        )
+    }
+
+    pub fn set_archive(&mut self, archive_mode: ArchiveMode, archive_level: i64) -> Self {
+        self.archive_mode = archive_mode;
+        self.archive_level = archive_level;
+        self.to_owned()
+    }
+
+    pub fn get_archive(&self) -> (ArchiveMode, i64) {
+        (self.archive_mode, self.archive_level)
+    }
+
+    pub fn set_naming_templates(&mut self, folder_template: String, file_template: String) -> Self {
+        self.folder_template = folder_template;
+        self.file_template = file_template;
+        self.to_owned()
+    }
+
+    pub fn get_naming_templates(&self) -> (String, String) {
+        (self.folder_template.clone(), self.file_template.clone())
+    }
+
+    pub fn set_existing_policy(&mut self, existing_policy: ExistingPolicy) -> Self {
+        self.existing_policy = existing_policy;
+        self.to_owned()
+    }
+
+    pub fn get_existing_policy(&self) -> ExistingPolicy {
+        self.existing_policy
+    }
+
+    /// Appending needs appendable formats and files which stay in the output folder.
+    pub fn can_append(&self) -> bool {
+        self.chunk_mode == ChunkMode::Off
+            && self.formats.iter().all(|format| format.is_appendable())
+            && matches!(
+                self.archive_mode,
+                ArchiveMode::None | ArchiveMode::Alongside
+            )
+    }
+
+    pub fn set_columns(&mut self, columns: Vec<String>, drop_category_columns: bool) -> Self {
+        self.columns = columns;
+        self.drop_category_columns = drop_category_columns;
+        self.to_owned()
+    }
+
+    pub fn get_columns(&self) -> (Vec<String>, bool) {
+        (self.columns.clone(), self.drop_category_columns)
+    }
+
+    pub fn set_header_renames(&mut self, header_renames: Vec<(String, String)>) -> Self {
+        self.header_renames = header_renames;
+        self.to_owned()
+    }
+
+    pub fn get_header_renames(&self) -> Vec<(String, String)> {
+        self.header_renames.clone()
+    }
+
+    pub fn set_selected_category(&mut self, selected_category: String) -> Self {
+        self.selected_category = selected_category;
+        self.to_owned()
+    }
+
+    pub fn set_cleaning_rules(&mut self, cleaning_rules: Vec<String>) -> Self {
+        self.cleaning_rules = cleaning_rules;
+        self.to_owned()
+    }
+
+    pub fn get_cleaning_rules(&self) -> Vec<String> {
+        self.cleaning_rules.clone()
+    }
+
+    pub fn set_lookup(
+        &mut self,
+        file: Option<PathBuf>,
+        input_key: String,
+        reference_key: String,
+        join: JoinKind,
+    ) -> Self {
+        self.lookup_file = file;
+        self.lookup_keys = (input_key, reference_key);
+        self.lookup_join = join;
+        self.to_owned()
+    }
+
+    pub fn get_lookup(&self) -> (Option<PathBuf>, String, String, JoinKind) {
+        (
+            self.lookup_file.clone(),
+            self.lookup_keys.0.clone(),
+            self.lookup_keys.1.clone(),
+            self.lookup_join,
+        )
+    }
+
+    pub fn set_derived_columns(&mut self, derived_columns: Vec<(String, String)>) -> Self {
+        self.derived_columns = derived_columns;
+        self.to_owned()
+    }
+
+    pub fn get_derived_columns(&self) -> Vec<(String, String)> {
+        self.derived_columns.clone()
+    }
+
+    pub fn set_masking(&mut self, masks: Vec<String>, salt: String) -> Self {
+        self.masks = masks;
+        self.mask_salt = salt;
+        self.to_owned()
+    }
+
+    pub fn get_masking(&self) -> (Vec<String>, String) {
+        (self.masks.clone(), self.mask_salt.clone())
+    }
+
+    pub fn set_sort_keys(&mut self, sort_keys: Vec<(String, bool)>) -> Self {
+        self.sort_keys = sort_keys;
+        self.to_owned()
+    }
+
+    pub fn get_sort_keys(&self) -> Vec<(String, bool)> {
+        self.sort_keys.clone()
+    }
+
+    pub fn set_statistics_columns(&mut self, statistics_columns: Vec<String>) -> Self {
+        self.statistics_columns = statistics_columns;
+        self.to_owned()
+    }
+
+    pub fn get_statistics_columns(&self) -> Vec<String> {
+        self.statistics_columns.clone()
+    }
+
+    pub fn set_crosstab(&mut self, column: Option<String>, sum: Option<String>) -> Self {
+        self.crosstab_column = column;
+        self.crosstab_sum = sum;
+        self.to_owned()
+    }
+
+    pub fn get_crosstab(&self) -> (Option<String>, Option<String>) {
+        (self.crosstab_column.clone(), self.crosstab_sum.clone())
+    }
+
+    pub fn set_dedup(
+        &mut self,
+        scope: DedupScope,
+        keys: Vec<String>,
+        keep_last: bool,
+        write_duplicates: bool,
+    ) -> Self {
+        self.dedup_scope = scope;
+        self.dedup_keys = keys;
+        self.dedup_keep_last = keep_last;
+        self.write_duplicates = write_duplicates;
+        self.to_owned()
+    }
+
+    pub fn get_dedup(&self) -> (DedupScope, Vec<String>, bool, bool) {
+        (
+            self.dedup_scope,
+            self.dedup_keys.clone(),
+            self.dedup_keep_last,
+            self.write_duplicates,
+        )
+    }
+
+    /// Output name of an input header.
+    pub fn get_header_name(&self, header: &str) -> String {
+        self.header_renames
+            .iter()
+            .find(|(from, _)| from == header)
+            .map_or(header, |(_, to)| to)
+            .to_string()
+    }
+
+    pub fn set_chunking(&mut self, chunk_mode: ChunkMode, chunk_size: usize) -> Self {
+        self.chunk_mode = chunk_mode;
+        self.chunk_size = chunk_size;
+        self.to_owned()
+    }
+
+    pub fn get_chunking(&self) -> (ChunkMode, usize) {
+        (self.chunk_mode, self.chunk_size)
+    }
+
+    pub fn set_manifest(&mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self.to_owned()
+    }
+
+    pub fn get_manifest(&self) -> bool {
+        self.manifest
+    }
+
+    /// All settings of the run for the manifest.
+    pub fn to_json(&self) -> JsonValue {
+        json!({
+            "category": self.selected_category,
+            "filter": self.filter.as_ref().map(|(field, value)| json!({
+                "field": field,
+                "value": value,
+            })),
+            "output": self.output.to_string_lossy(),
+            "cleaning_rules": self.cleaning_rules,
+            "lookup": self.lookup_file.as_ref().map(|file| json!({
+                "file": file.to_string_lossy(),
+                "input_key": self.lookup_keys.0,
+                "reference_key": self.lookup_keys.1,
+                "join": self.lookup_join.label(),
+            })),
+            "derived_columns": self
+                .derived_columns
+                .iter()
+                .map(|(name, expression)| (name.clone(), JsonValue::from(expression.clone())))
+                .collect::<Map<String, JsonValue>>(),
+            "columns": self.columns,
+            "drop_category_columns": self.drop_category_columns,
+            "header_renames": self
+                .header_renames
+                .iter()
+                .map(|(from, to)| (from.clone(), JsonValue::from(to.clone())))
+                .collect::<Map<String, JsonValue>>(),
+            // the salt stays secret, otherwise hashes could be recomputed
+            "masks": self.masks,
+            "sort": self
+                .sort_keys
+                .iter()
+                .map(|(column, descending)| json!({
+                    "column": column,
+                    "descending": descending,
+                }))
+                .collect::<Vec<_>>(),
+            "statistics": self.statistics_columns,
+            "crosstab": self.crosstab_column.as_ref().map(|column| json!({
+                "column": column,
+                "sum": self.crosstab_sum,
+            })),
+            "deduplicate": match self.dedup_scope {
+                DedupScope::Off => JsonValue::Null,
+                scope => json!({
+                    "scope": scope.label(),
+                    "keys": self.dedup_keys,
+                    "keep": if self.dedup_keep_last { "last" } else { "first" },
+                    "write_duplicates": self.write_duplicates,
+                }),
+            },
+            "parts": match self.chunk_mode {
+                ChunkMode::Off => JsonValue::Null,
+                ChunkMode::Rows => json!({ "rows": self.chunk_size }),
+                ChunkMode::Bytes => json!({ "bytes": self.chunk_size }),
+            },
+            "formats": self.formats.iter().map(|format| format.label()).collect::<Vec<_>>(),
+            "infer_types": self.infer_types,
+            "date_patterns": self.date_patterns,
+            "excel": {
+                "date_format": self.excel_date_format,
+                "datetime_format": self.excel_datetime_format,
+                "table_style": self.excel_table_style,
+                "formulas": self.xlsx_formula_policy.label(),
+            },
+            "csv_dialect": {
+                "delimiter": self.csv_dialect.delimiter,
+                "quote_style": self.csv_dialect.quote_style,
+                "line_ending": self.csv_dialect.line_ending,
+                "bom": self.csv_dialect.bom,
+                "formulas": self.csv_formula_policy.label(),
+            },
+            "sqlite_layout": self.sqlite_layout.label(),
+            "parquet": {
+                "compression": self.parquet_compression,
+                "row_group_size": self.parquet_row_group_size,
+            },
+            "archive": {
+                "mode": self.archive_mode.label(),
+                "level": self.archive_level,
+            },
+            "existing_policy": self.existing_policy.label(),
+            "naming": {
+                "folder": self.folder_template,
+                "files": self.file_template,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::get_headers_from_file;
+    use arrow_ipc::reader::FileReader;
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::json;
+    use std::{fs, path::Path};
+    use zip::ZipArchive;
+
+    /// Nord and nord are the same category, named after the first row.
+    const INPUT: &str = "Region;Name;Amount\nNord;Anna;1,5\nSüd;Ben;2\nnord;Carla;\n";
+
+    /// Empty folder in the temp dir with `input.csv`, which also receives the output.
+    fn folder(name: &str, input: &str) -> PathBuf {
+        let folder =
+            std::env::temp_dir().join(format!("csv-helper-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("input.csv"), input).unwrap();
+        folder
+    }
+
+    /// Splits `input.csv` by Region into `folder`.
+    fn options(folder: &Path) -> Options {
+        Options::new(
+            "Region".to_string(),
+            folder.join("input.csv"),
+            folder.to_path_buf(),
+            None,
+        )
+    }
+
+    fn read(path: PathBuf) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn run(options: Options) -> Result<Summary, Box<dyn Error>> {
+        let siv = Cursive::new();
+        let headers = get_headers_from_file(&options.input)?;
+        Transformer::new(siv.cb_sink().clone(), options, headers).execute()
+    }
+
+    #[test]
+    fn column_patterns_replace_general_ones() {
+        let mut options = Options::new("Region".to_string(), PathBuf::new(), PathBuf::new(), None);
+        options.set_date_patterns(vec![
+            "%d/%m/%Y".to_string(),
+            "Born = %Y%m%d".to_string(),
+            "Born = %Y".to_string(),
+            "%Y-%m-%d".to_string(),
+        ]);
+        assert_eq!(options.get_date_patterns("Born"), vec!["%Y%m%d", "%Y"]);
+        assert_eq!(
+            options.get_date_patterns("Day"),
+            vec!["%d/%m/%Y", "%Y-%m-%d"]
+        );
+    }
+
+    #[test]
+    fn csv_files_in_configured_dialect() {
+        let folder = folder("dialect", INPUT);
+        run(options(&folder)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Region;Name;Amount\nNord;Anna;1,5\nnord;Carla;\n"
+        );
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        let dialect = CsvDialect {
+            delimiter: "Comma".to_string(),
+            quote_style: "Always".to_string(),
+            line_ending: "CRLF".to_string(),
+            bom: true,
+        };
+        run(options(&folder).set_csv_dialect(dialect)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Süd.csv")),
+            "\u{feff}\"Region\",\"Name\",\"Amount\"\r\n\"Süd\",\"Ben\",\"2\"\r\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn json_and_ndjson_with_typed_values() {
+        let folder = folder(
+            "json",
+            "Region;Name;Amount;Count;Day\nNord;Anna;1,5;3;5.3.2024\nNord;Ben;2;x;6.3.2024 14:30:00\n",
+        );
+        let formats = vec![OutputFormat::Json, OutputFormat::Ndjson];
+        run(options(&folder).set_formats(formats.clone())).unwrap();
+        let expected = json!([
+            {"Region": "Nord", "Name": "Anna", "Amount": 1.5, "Count": "3", "Day": "2024-03-05"},
+            {"Region": "Nord", "Name": "Ben", "Amount": 2.0, "Count": "x", "Day": "2024-03-06T14:30:00"},
+        ]);
+        let json: JsonValue =
+            serde_json::from_str(&read(folder.join("Region").join("Nord.json"))).unwrap();
+        assert_eq!(json, expected);
+        let lines: Vec<JsonValue> = read(folder.join("Region").join("Nord.ndjson"))
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(JsonValue::from(lines), expected);
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(options(&folder).set_formats(formats).set_infer_types(false)).unwrap();
+        let json: JsonValue =
+            serde_json::from_str(&read(folder.join("Region").join("Nord.json"))).unwrap();
+        assert_eq!(json[0]["Amount"], "1,5");
+        assert_eq!(json[1]["Day"], "6.3.2024 14:30:00");
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    /// Name and amount of every row of a query.
+    fn query(path: &Path, sql: &str) -> Vec<(String, Option<f64>)> {
+        let connection = Connection::open(path).unwrap();
+        let mut statement = connection.prepare(sql).unwrap();
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn sqlite_tables_per_category_or_single() {
+        let folder = folder("sqlite", INPUT);
+        let database = folder.join("Region").join("Region.sqlite");
+        run(options(&folder).set_formats(vec![OutputFormat::Sqlite])).unwrap();
+        assert_eq!(
+            query(&database, "SELECT Name, Amount FROM Nord"),
+            vec![("Anna".to_string(), Some(1.5)), ("Carla".to_string(), None)]
+        );
+        assert_eq!(
+            query(&database, "SELECT Name, Amount FROM \"Süd\""),
+            vec![("Ben".to_string(), Some(2.0))]
+        );
+        assert_eq!(
+            query(
+                &database,
+                "SELECT value, NULL FROM run_metadata WHERE key = 'category'"
+            ),
+            vec![("Region".to_string(), None)]
+        );
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(options(&folder)
+            .set_formats(vec![OutputFormat::Sqlite])
+            .set_sqlite_layout(SqliteLayout::SingleTable))
+        .unwrap();
+        assert_eq!(
+            query(
+                &database,
+                "SELECT Region, Amount FROM records ORDER BY Name"
+            ),
+            vec![
+                ("Nord".to_string(), Some(1.5)),
+                ("Süd".to_string(), Some(2.0)),
+                ("nord".to_string(), None)
+            ]
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn sqlite_table_names_unique() {
+        let folder = folder(
+            "sqlite-names",
+            "Region;Name\n;Anna\nEmpty_Region;Ben\nsqlite_x;Carla\nt_sqlite_X;Dora\nrun_metadata;Emil\n",
+        );
+        let database = folder.join("Region").join("Region.sqlite");
+        run(options(&folder).set_formats(vec![OutputFormat::Sqlite])).unwrap();
+        assert_eq!(
+            query(
+                &database,
+                "SELECT name, NULL FROM sqlite_master WHERE type = 'table' ORDER BY rowid"
+            ),
+            vec![
+                ("Empty_Region".to_string(), None),
+                ("Empty_Region_2".to_string(), None),
+                ("run_metadata_2".to_string(), None),
+                ("t_sqlite_x".to_string(), None),
+                ("t_sqlite_X_2".to_string(), None),
+                ("run_metadata".to_string(), None)
+            ]
+        );
+        assert_eq!(
+            query(&database, "SELECT Name, NULL FROM t_sqlite_X_2"),
+            vec![("Dora".to_string(), None)]
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn parquet_and_arrow_with_inferred_schema() {
+        let folder = folder("columnar", INPUT);
+        run(options(&folder)
+            .set_formats(vec![OutputFormat::Parquet, OutputFormat::Arrow])
+            .set_parquet_settings("Zstd".to_string(), 1))
+        .unwrap();
+
+        let file = File::open(folder.join("Region").join("Nord.parquet")).unwrap();
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(builder.metadata().num_row_groups(), 2);
+        assert_eq!(builder.schema().field(2).data_type(), &DataType::Float64);
+        let rows: usize = builder
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 2);
+
+        let file = File::open(folder.join("Region").join("Süd.arrow")).unwrap();
+        let batches: Vec<_> = FileReader::try_new(file, None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 1);
+        assert_eq!(batches[0].schema().field(1).name(), "Name");
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    fn zip_entries(path: PathBuf) -> Vec<String> {
+        let archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        archive.file_names().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn archive_instead_of_folder_or_per_category() {
+        let folder = folder("archive", INPUT);
+        run(options(&folder).set_archive(ArchiveMode::Instead, 9)).unwrap();
+        assert!(!folder.join("Region").exists());
+        let entries = zip_entries(folder.join("Region.zip"));
+        assert!(entries.contains(&"Region/Nord.csv".to_string()));
+        assert!(entries.contains(&"Region/Süd.xlsx".to_string()));
+
+        run(options(&folder).set_archive(ArchiveMode::PerCategory, 0)).unwrap();
+        assert!(!folder.join("Region").join("Nord.csv").exists());
+        let mut entries = zip_entries(folder.join("Region").join("Nord.zip"));
+        entries.sort();
+        assert_eq!(entries, vec!["Nord.csv", "Nord.xlsx"]);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn folder_and_files_named_by_templates() {
+        let folder = folder("naming", INPUT);
+        run(options(&folder).set_naming_templates(
+            "{input_stem}_{category}_{filter}".to_string(),
+            "{index}-{category}_{rowcount}".to_string(),
+        ))
+        .unwrap();
+        let output = folder.join("input_Region");
+        assert_eq!(
+            read(output.join("1-Nord_2.csv")),
+            "Region;Name;Amount\nNord;Anna;1,5\nnord;Carla;\n"
+        );
+        assert!(output.join("2-Süd_1.xlsx").is_file());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn policies_for_existing_folders() {
+        let folder = folder("existing", INPUT);
+        run(options(&folder)).unwrap();
+        assert!(run(options(&folder)).unwrap_err().is::<DirectoryError>());
+
+        run(options(&folder).set_existing_policy(ExistingPolicy::Number)).unwrap();
+        run(options(&folder).set_existing_policy(ExistingPolicy::Number)).unwrap();
+        assert!(folder.join("Region_2").join("Nord.csv").is_file());
+        assert!(folder.join("Region_3").join("Süd.csv").is_file());
+
+        fs::write(folder.join("Region").join("notes.txt"), "notes").unwrap();
+        run(options(&folder).set_existing_policy(ExistingPolicy::Overwrite)).unwrap();
+        assert!(!folder.join("Region").join("notes.txt").exists());
+
+        run(options(&folder)
+            .set_formats(vec![OutputFormat::Csv])
+            .set_existing_policy(ExistingPolicy::Append))
+        .unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Süd.csv")),
+            "Region;Name;Amount\nSüd;Ben;2\nSüd;Ben;2\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn manifest_lists_written_files() {
+        let folder = folder("run-manifest", INPUT);
+        run(options(&folder)).unwrap();
+        let manifest: JsonValue =
+            serde_json::from_str(&read(folder.join("Region").join(MANIFEST_FILE))).unwrap();
+        let files: Vec<(&str, &str, u64)> = manifest["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|file| {
+                (
+                    file["file"].as_str().unwrap(),
+                    file["category"].as_str().unwrap(),
+                    file["rows"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("Nord.csv", "Nord", 2),
+                ("Nord.xlsx", "Nord", 2),
+                ("Süd.csv", "Süd", 1),
+                ("Süd.xlsx", "Süd", 1)
+            ]
+        );
+        assert_eq!(manifest["options"]["category"], "Region");
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(options(&folder).set_manifest(false)).unwrap();
+        assert!(!folder.join("Region").join(MANIFEST_FILE).exists());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    fn entries(folder: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn failed_run_keeps_existing_output() {
+        let folder = folder("staging", INPUT);
+        fs::create_dir(folder.join("Region")).unwrap();
+        fs::write(folder.join("Region").join("Nord.csv"), "Other\nx\n").unwrap();
+        let error = run(options(&folder)
+            .set_formats(vec![OutputFormat::Csv])
+            .set_existing_policy(ExistingPolicy::Append))
+        .unwrap_err();
+        assert!(error.is::<HeaderMismatchError>());
+        assert_eq!(entries(&folder), vec!["Region", "input.csv"]);
+        assert_eq!(entries(&folder.join("Region")), vec!["Nord.csv"]);
+        assert_eq!(read(folder.join("Region").join("Nord.csv")), "Other\nx\n");
+
+        let siv = Cursive::new();
+        let headers = get_headers_from_file(&folder.join("input.csv")).unwrap();
+        let mut transformer = Transformer::new(
+            siv.cb_sink().clone(),
+            options(&folder).set_existing_policy(ExistingPolicy::Overwrite),
+            headers,
+        );
+        transformer.get_cancel_flag().store(true, Ordering::Relaxed);
+        assert!(transformer.execute().unwrap_err().is::<CancelError>());
+        assert_eq!(entries(&folder), vec!["Region", "input.csv"]);
+        assert_eq!(read(folder.join("Region").join("Nord.csv")), "Other\nx\n");
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn colliding_file_names_are_numbered() {
+        let folder = folder(
+            "collisions",
+            "Region;Name\na/b;Anna\na:b;Ben\nA_B;Carla\nmanifest;Dora\n",
+        );
+        run(options(&folder).set_formats(vec![OutputFormat::Csv, OutputFormat::Json])).unwrap();
+        let output = folder.join("Region");
+        assert_eq!(
+            entries(&output),
+            vec![
+                "A_B.csv",
+                "A_B.json",
+                "a_b_2.csv",
+                "a_b_2.json",
+                "a_b_3.csv",
+                "a_b_3.json",
+                "manifest.json",
+                "manifest_2.csv",
+                "manifest_2.json"
+            ]
+        );
+        assert_eq!(read(output.join("a_b_3.csv")), "Region;Name\na:b;Ben\n");
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn long_names_fit_with_suffixes() {
+        let (header, category) = ("R".repeat(300), "ä".repeat(150));
+        let input = format!("{};Name\n{}/x;Anna\n{}:x;Ben\n", header, category, category);
+        let folder = folder("long-names", &input);
+        let options = || {
+            Options::new(
+                header.clone(),
+                folder.join("input.csv"),
+                folder.clone(),
+                None,
+            )
+            .set_formats(vec![OutputFormat::Csv, OutputFormat::Parquet])
+            .set_existing_policy(ExistingPolicy::Timestamp)
+        };
+        run(options()).unwrap();
+        run(options()).unwrap();
+        run(options()).unwrap();
+
+        let folders = entries(&folder);
+        assert_eq!(folders.len(), 4);
+        for name in folders.iter().filter(|name| name.starts_with('R')) {
+            assert!(name.len() + ".zip".len() + SIBLING_BYTES <= 255);
+            let files = entries(&folder.join(name));
+            assert_eq!(files.len(), 5);
+            assert!(files.iter().all(|file| file.len() + SIBLING_BYTES <= 255));
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn selected_columns_in_order() {
+        let folder = folder("columns", INPUT);
+        run(options(&folder).set_columns(vec!["Amount".to_string(), "Name".to_string()], false))
+            .unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Amount;Name\n1,5;Anna\n;Carla\n"
+        );
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(options(&folder).set_columns(vec![], true)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Süd.csv")),
+            "Name;Amount\nBen;2\n"
+        );
+        assert!(run(options(&folder).set_columns(vec!["Town".to_string()], false)).is_err());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn renamed_headers() {
+        let folder = folder("renames", INPUT);
+        run(options(&folder)
+            .set_columns(vec!["Name".to_string(), "Amount".to_string()], false)
+            .set_header_renames(vec![("Amount".to_string(), "Betrag".to_string())]))
+        .unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Süd.csv")),
+            "Name;Betrag\nBen;2\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn duplicates_removed_and_written() {
+        let folder = folder(
+            "dedup",
+            "Region;Name;Amount\nNord;Anna;1\nSüd;Anna;2\nNord;Ben;3\nnord;Anna;4\n",
+        );
+        let summary = run(options(&folder).set_dedup(
+            DedupScope::Global,
+            vec!["Name".to_string()],
+            false,
+            true,
+        ))
+        .unwrap();
+        assert_eq!(summary.duplicates, Some(2));
+        let output = folder.join("Region");
+        assert_eq!(
+            read(output.join("Nord.csv")),
+            "Region;Name;Amount\nNord;Anna;1\nNord;Ben;3\n"
+        );
+        // all rows of Süd are duplicates of Nord
+        assert!(!output.join("Süd.csv").exists());
+        assert_eq!(
+            read(output.join(DUPLICATES_FILE)),
+            "Region;Name;Amount\nSüd;Anna;2\nnord;Anna;4\n"
+        );
+
+        fs::remove_dir_all(&output).unwrap();
+        run(options(&folder).set_dedup(
+            DedupScope::PerCategory,
+            vec!["Name".to_string()],
+            true,
+            false,
+        ))
+        .unwrap();
+        assert_eq!(
+            read(output.join("Nord.csv")),
+            "Region;Name;Amount\nNord;Ben;3\nnord;Anna;4\n"
+        );
+        assert_eq!(
+            read(output.join("Süd.csv")),
+            "Region;Name;Amount\nSüd;Anna;2\n"
+        );
+        assert!(!output.join(DUPLICATES_FILE).exists());
+
+        // replaced rows keep the columns which aren't written
+        fs::remove_dir_all(&output).unwrap();
+        run(options(&folder)
+            .set_columns(vec!["Name".to_string()], false)
+            .set_dedup(
+                DedupScope::PerCategory,
+                vec!["Name".to_string()],
+                true,
+                true,
+            ))
+        .unwrap();
+        assert_eq!(read(output.join("Nord.csv")), "Name\nBen\nAnna\n");
+        assert_eq!(
+            read(output.join(DUPLICATES_FILE)),
+            "Region;Name;Amount\nNord;Anna;1\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn statistics_without_replaced_rows() {
+        let folder = folder(
+            "statistics",
+            "Region;Name;Amount\nNord;Anna;1\nSüd;Anna;2\nNord;Ben;3\nnord;Anna;4,5\n",
+        );
+        let summary = run(options(&folder)
+            .set_statistics_columns(vec!["Amount".to_string()])
+            .set_dedup(
+                DedupScope::PerCategory,
+                vec!["Name".to_string()],
+                true,
+                false,
+            ))
+        .unwrap();
+        let sums: Vec<(&str, usize, Option<f64>)> = summary
+            .statistics
+            .iter()
+            .map(|(category, columns)| (category.as_str(), columns[0].1.count, columns[0].1.sum()))
+            .collect();
+        assert_eq!(sums, vec![("Nord", 2, Some(7.5)), ("Süd", 1, Some(2.0))]);
+        assert_eq!(
+            read(folder.join("Region").join(STATISTICS_CSV))
+                .lines()
+                .nth(1),
+            Some("Nord;Amount;2;7.5;3.75;3;4.5;2")
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn formulas_escaped_unless_kept() {
+        let folder = folder(
+            "formulas",
+            "Region;Name;Amount\nNord;=SUM(A1);-7,25\nNord;@Anna;+1\n",
+        );
+        run(options(&folder)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Region;Name;Amount\nNord;'=SUM(A1);-7,25\nNord;'@Anna;+1\n"
+        );
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(options(&folder).set_formula_policies(FormulaPolicy::Keep, FormulaPolicy::Escape))
+            .unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Region;Name;Amount\nNord;=SUM(A1);-7,25\nNord;@Anna;+1\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn left_or_inner_join() {
+        let folder = folder(
+            "join",
+            "Region;Name;Customer\nNord;Anna;1\nSüd;Ben;2\nNord;Carla;9\n",
+        );
+        let reference = folder.join("customers.csv");
+        fs::write(&reference, "Id,Segment\n1,Retail\n2,Business\n").unwrap();
+        let join = |kind: JoinKind| {
+            options(&folder).set_lookup(
+                Some(reference.clone()),
+                "Customer".to_string(),
+                "Id".to_string(),
+                kind,
+            )
+        };
+
+        let summary = run(join(JoinKind::Left)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Region;Name;Customer;Segment\nNord;Anna;1;Retail\nNord;Carla;9;\n"
+        );
+        assert_eq!(summary.unmatched_keys, Some(vec![("9".to_string(), 1)]));
+
+        fs::remove_dir_all(folder.join("Region")).unwrap();
+        run(join(JoinKind::Inner)).unwrap();
+        assert_eq!(
+            read(folder.join("Region").join("Nord.csv")),
+            "Region;Name;Customer;Segment\nNord;Anna;1;Retail\n"
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn formulas_escaped_in_duplicates() {
+        let folder = folder(
+            "duplicate-formulas",
+            "Region;Name;Amount\nNord;Anna;1\nNord;=SUM(A1);2\nNord;=SUM(A1);3\n",
+        );
+        let summary = run(options(&folder).set_dedup(
+            DedupScope::Global,
+            vec!["Name".to_string()],
+            false,
+            true,
+        ))
+        .unwrap();
+        assert_eq!(
+            read(folder.join("Region").join(DUPLICATES_FILE)),
+            "Region;Name;Amount\nNord;'=SUM(A1);3\n"
+        );
+        let files: Vec<&str> = summary
+            .formula_cells
+            .iter()
+            .map(|cell| cell.file.as_str())
+            .collect();
+        assert!(files.contains(&DUPLICATES_FILE));
+        fs::remove_dir_all(folder).unwrap();
     }
 }
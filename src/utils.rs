@@ -1,14 +1,79 @@
-use chrono::{Datelike, NaiveDateTime, Timelike};
-use csv::{Reader, StringRecord};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use csv::{QuoteStyle, Reader, StringRecord, Terminator};
 use native_dialog::FileDialog;
-use std::{error::Error, fs::File, path::{PathBuf, Path}};
+use rust_xlsxwriter::{utility::check_name, ExcelDateTime, TableStyle, XlsxError};
+use std::{
+    error::Error,
+    fs::File,
+    path::{Path, PathBuf},
+};
 
-use crate::errors::HeaderError;
+use crate::{errors::HeaderError, infer::parse_number};
+
+/// Patterns used for date recognition unless configured otherwise.
+pub const DEFAULT_DATE_PATTERNS: [&str; 6] = [
+    "%-d.%-m.%Y %H:%M:%S",
+    "%-d.%-m.%Y",
+    "%+",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d",
+];
+
+/// Excel formats for date cells, the first one is the default.
+pub const EXCEL_DATE_FORMATS: [&str; 4] = ["dd.mm.yyyy", "yyyy-mm-dd", "mm/dd/yyyy", "dd/mm/yyyy"];
+
+/// Excel formats for date cells with a time part, the first one is the default.
+pub const EXCEL_DATETIME_FORMATS: [&str; 4] = [
+    "dd.mm.yyyy hh:mm:ss",
+    "yyyy-mm-dd hh:mm:ss",
+    "mm/dd/yyyy hh:mm:ss",
+    "dd/mm/yyyy hh:mm:ss",
+];
+
+/// Styles offered for Excel tables.
+pub const EXCEL_TABLE_STYLES: [(&str, TableStyle); 6] = [
+    ("Light 9", TableStyle::Light9),
+    ("Light 15", TableStyle::Light15),
+    ("Medium 2", TableStyle::Medium2),
+    ("Medium 9", TableStyle::Medium9),
+    ("Medium 16", TableStyle::Medium16),
+    ("Dark 1", TableStyle::Dark1),
+];
+
+/// Delimiters offered for the written CSV files, the first one is the default.
+pub const CSV_DELIMITERS: [(&str, u8); 4] = [
+    ("Semicolon", b';'),
+    ("Comma", b','),
+    ("Tab", b'\t'),
+    ("Pipe", b'|'),
+];
+
+pub const CSV_QUOTE_STYLES: [(&str, QuoteStyle); 4] = [
+    ("Necessary", QuoteStyle::Necessary),
+    ("Always", QuoteStyle::Always),
+    ("Non numeric", QuoteStyle::NonNumeric),
+    ("Never", QuoteStyle::Never),
+];
+
+pub const CSV_LINE_ENDINGS: [(&str, Terminator); 2] =
+    [("LF", Terminator::Any(b'\n')), ("CRLF", Terminator::CRLF)];
+
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
 
 const INVALID_CHARS: [char; 14] = [
     '$', '%', '^', '*', '/', ' ', '.', ':', '<', '>', '"', '\\', '|', '?',
 ];
 
+/// Device names Windows doesn't allow as file name, with any extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// File systems allow 255 bytes per name.
+const MAX_NAME_BYTES: usize = 255;
+
 pub fn select_file() -> Result<PathBuf, String> {
     match FileDialog::new()
         .set_location("~/Downloads")
@@ -33,29 +98,117 @@ pub fn select_directory() -> Result<PathBuf, String> {
     }
 }
 
+/// Position of a column, names are matched exactly as joined and derived columns may contain
+/// input names.
 pub trait Header {
     fn get_field(&mut self, field: &str) -> Result<usize, Box<dyn Error>>;
 }
 
 impl Header for Reader<File> {
     fn get_field(&mut self, name: &str) -> Result<usize, Box<dyn Error>> {
-        match self
-            .headers()?
-            .iter()
-            .position(|field| field.contains(&name))
-        {
+        match self.headers()?.iter().position(|field| field == name) {
             Some(idx) => Ok(idx),
             None => Err(Box::new(HeaderError)),
         }
     }
 }
 
+impl Header for StringRecord {
+    fn get_field(&mut self, name: &str) -> Result<usize, Box<dyn Error>> {
+        match self.iter().position(|field| field == name) {
+            Some(idx) => Ok(idx),
+            None => Err(Box::new(HeaderError)),
+        }
+    }
+}
+
+/// First characters which make spreadsheet applications evaluate a value as formula.
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Whether a value would be evaluated as formula. Signed numbers like `-7,25` are fine.
+pub fn is_formula(field: &str) -> bool {
+    field.starts_with(&FORMULA_PREFIXES[..]) && parse_number(field).is_none()
+}
+
+/// Turns a category into a file or folder name which is valid on Windows, macOS and Linux,
+/// as the output is usually passed on.
+///
+/// Invalid and control characters become `_`, which includes dots and spaces, and reserved
+/// device names get a `_` suffix. Long names are cut with [`truncate_name`] once their
+/// suffixes are known.
 pub fn replace_all_invalid_characters(field: &str) -> String {
-    let mut field = String::from(field);
-    INVALID_CHARS
+    let mut field: String = field
+        .chars()
+        .map(|c| match INVALID_CHARS.contains(&c) || c.is_control() {
+            true => '_',
+            false => c,
+        })
+        .collect();
+    if RESERVED_NAMES
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(&field))
+    {
+        field.push('_');
+    }
+    match field.is_empty() {
+        true => "_".to_string(),
+        false => field,
+    }
+}
+
+/// Cuts `name` at a character boundary, so it fits [`MAX_NAME_BYTES`] with `suffix_bytes`
+/// added, like numbering and extension.
+pub fn truncate_name(name: &str, suffix_bytes: usize) -> &str {
+    let max = MAX_NAME_BYTES.saturating_sub(suffix_bytes);
+    let end = (0..=max.min(name.len()))
+        .rev()
+        .find(|&idx| name.is_char_boundary(idx))
+        .unwrap();
+    &name[..end]
+}
+
+/// Turns a category into a valid Excel table name.
+///
+/// Invalid characters become `_`. Names Excel still rejects (leading digit, cell references
+/// like `A1`, `TRUE`) get the prefix `T_`.
+pub fn excel_table_name(category: &str) -> String {
+    let mut name: String = category
+        .chars()
+        .map(|c| match c.is_alphanumeric() || c == '_' || c == '.' {
+            true => c,
+            false => '_',
+        })
+        .take(250)
+        .collect();
+    if name.is_empty() {
+        name = String::from("Empty");
+    }
+    match check_name(&name) {
+        Ok(_) => name,
+        Err(_) => format!("T_{}", name),
+    }
+}
+
+/// Headers made unique (ignoring case) and not empty, as required for tables and JSON keys.
+pub fn unique_headers(headers: &StringRecord) -> Vec<String> {
+    let mut seen: Vec<String> = vec![];
+    headers
         .iter()
-        .for_each(|&c| field = field.replace(c, "_"));
-    field
+        .enumerate()
+        .map(|(idx, header)| {
+            let base = match header.trim().is_empty() {
+                true => format!("Column{}", idx + 1),
+                false => header.to_string(),
+            };
+            let (mut name, mut count) = (base.clone(), 1);
+            while seen.contains(&name.to_lowercase()) {
+                count += 1;
+                name = format!("{} {}", base, count);
+            }
+            seen.push(name.to_lowercase());
+            name
+        })
+        .collect()
 }
 
 pub fn get_headers_from_file(file: &Path) -> Result<StringRecord, Box<dyn Error>> {
@@ -65,28 +218,229 @@ pub fn get_headers_from_file(file: &Path) -> Result<StringRecord, Box<dyn Error>
     Err(Box::new(HeaderError))
 }
 
-pub fn try_parse_time(field: &str) -> Result<xlsxwriter::DateTime, ()> {
-    match NaiveDateTime::parse_from_str(field, "%-d.%-m.%Y %H:%M:%S") {
-        Ok(datetime) => {
-            let d = datetime.date();
-            let t = datetime.time();
-            let (year, month, day, hour, minute, second) = (
-                d.year() as i16,
-                d.month() as i8,
-                d.day() as i8,
-                t.hour() as i8,
-                t.minute() as i8,
-                t.second(),
-            );
-            Ok(xlsxwriter::DateTime::new(
-                year,
-                month,
-                day,
-                hour,
-                minute,
-                second.into(),
-            ))
+/// First records of the input without header, for previews.
+pub fn read_sample(file: &Path, rows: usize) -> Result<Vec<StringRecord>, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new().delimiter(b';').from_path(file)?;
+    Ok(rdr.records().take(rows).collect::<Result<_, _>>()?)
+}
+
+/// Reads `input header, output name` pairs from a CSV file without header row.
+///
+/// The delimiter is `;` like for the input, or `,` if the first line has no `;`.
+pub fn read_header_renames(file: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let content = std::fs::read_to_string(file)?;
+    let delimiter = match content.lines().next().unwrap_or_default().contains(';') {
+        true => b';',
+        false => b',',
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+    let mut renames = vec![];
+    for record in rdr.records() {
+        let record = record?;
+        if let (Some(from), Some(to)) = (record.get(0), record.get(1)) {
+            if !from.trim().is_empty() && !to.trim().is_empty() {
+                renames.push((from.trim().to_string(), to.trim().to_string()));
+            }
         }
-        Err(_) => Err(()),
+    }
+    Ok(renames)
+}
+
+/// Parsed value of a date field. Date-only values are written without a time part.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParsedTime {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl ParsedTime {
+    /// Date-only values at midnight, for comparisons.
+    pub fn naive(self) -> NaiveDateTime {
+        match self {
+            ParsedTime::Date(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+            ParsedTime::DateTime(datetime) => datetime,
+        }
+    }
+
+    /// ISO 8601 representation, without time for date-only values.
+    pub fn to_iso(self) -> String {
+        match self {
+            ParsedTime::Date(date) => date.format("%Y-%m-%d").to_string(),
+            ParsedTime::DateTime(datetime) => datetime.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+        }
+    }
+
+    /// Fails for dates before 1900 which Excel can't represent.
+    pub fn to_excel(self) -> Result<ExcelDateTime, XlsxError> {
+        let (d, t) = match self {
+            ParsedTime::Date(date) => (date, NaiveTime::from_hms_opt(0, 0, 0).unwrap()),
+            ParsedTime::DateTime(datetime) => (datetime.date(), datetime.time()),
+        };
+        let (year, month, day, hour, minute, second) = (
+            d.year() as u16,
+            d.month() as u8,
+            d.day() as u8,
+            t.hour() as u16,
+            t.minute() as u8,
+            t.second(),
+        );
+        ExcelDateTime::from_ymd(year, month, day)?.and_hms(hour, minute, second)
+    }
+}
+
+/// Tries every pattern in order (chrono syntax, see [`chrono::format::strftime`]).
+///
+/// Patterns with a time zone (`%z`, `%:z`) keep the local time of the field.
+pub fn try_parse_time(field: &str, patterns: &[String]) -> Result<ParsedTime, ()> {
+    for pattern in patterns {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(field, pattern) {
+            return Ok(ParsedTime::DateTime(datetime));
+        }
+        if let Ok(datetime) = DateTime::parse_from_str(field, pattern) {
+            return Ok(ParsedTime::DateTime(datetime.naive_local()));
+        }
+        if let Ok(date) = NaiveDate::parse_from_str(field, pattern) {
+            return Ok(ParsedTime::Date(date));
+        }
+    }
+    Err(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_dates_with_configured_patterns() {
+        let patterns = patterns(&["%d/%m/%Y %H:%M", "%d/%m/%Y", "%Y-%m-%d%:z"]);
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert_eq!(
+            try_parse_time("05/03/2024 10:30", &patterns),
+            Ok(ParsedTime::DateTime(date.and_hms_opt(10, 30, 0).unwrap()))
+        );
+        assert_eq!(
+            try_parse_time("05/03/2024", &patterns),
+            Ok(ParsedTime::Date(date))
+        );
+        assert_eq!(try_parse_time("2024-03-05", &patterns), Err(()));
+        assert_eq!(try_parse_time("5.3.2024", &patterns), Err(()));
+    }
+
+    #[test]
+    fn offsets_keep_the_local_time() {
+        let default = patterns(&DEFAULT_DATE_PATTERNS);
+        let datetime = NaiveDate::from_ymd_opt(2022, 3, 1)
+            .unwrap()
+            .and_hms_opt(14, 23, 22)
+            .unwrap();
+        assert_eq!(
+            try_parse_time("2022-03-01T14:23:22+05:00", &default),
+            Ok(ParsedTime::DateTime(datetime))
+        );
+        assert_eq!(
+            try_parse_time("1.3.2022 14:23:22", &default),
+            Ok(ParsedTime::DateTime(datetime))
+        );
+    }
+
+    #[test]
+    fn valid_table_names_and_headers() {
+        assert_eq!(excel_table_name("Süd-Ost"), "Süd_Ost");
+        assert_eq!(excel_table_name("2024 Nord"), "T_2024_Nord");
+        assert_eq!(excel_table_name("A1"), "T_A1");
+        assert_eq!(excel_table_name(""), "Empty");
+        assert_eq!(
+            unique_headers(&StringRecord::from(vec!["Name", "", "name", "Name 2"])),
+            vec!["Name", "Column2", "name 2", "Name 2 2"]
+        );
+    }
+
+    #[test]
+    fn iso_dates_without_midnight() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert_eq!(ParsedTime::Date(date).to_iso(), "2024-03-05");
+        assert_eq!(
+            ParsedTime::DateTime(date.and_hms_opt(0, 0, 0).unwrap()).to_iso(),
+            "2024-03-05T00:00:00"
+        );
+    }
+
+    #[test]
+    fn file_names_valid_on_all_platforms() {
+        assert_eq!(
+            replace_all_invalid_characters("Nord/Süd: 50%"),
+            "Nord_Süd__50_"
+        );
+        assert_eq!(replace_all_invalid_characters("tab\there"), "tab_here");
+        assert_eq!(replace_all_invalid_characters("con"), "con_");
+        assert_eq!(replace_all_invalid_characters("LPT1"), "LPT1_");
+        assert_eq!(replace_all_invalid_characters("console"), "console");
+        assert_eq!(replace_all_invalid_characters(""), "_");
+
+        assert_eq!(replace_all_invalid_characters("con.txt"), "con_txt");
+    }
+
+    #[test]
+    fn long_names_cut_before_suffixes() {
+        let long = "ä".repeat(200);
+        assert_eq!(truncate_name(&long, 0).len(), 254);
+        assert_eq!(truncate_name(&long, 15).len(), 240);
+        assert!(truncate_name(&long, 16).chars().all(|c| c == 'ä'));
+        assert_eq!(truncate_name("Nord", 15), "Nord");
+    }
+
+    #[test]
+    fn header_renames_from_csv() {
+        let file =
+            std::env::temp_dir().join(format!("csv-helper-renames-{}.csv", std::process::id()));
+        fs::write(&file, "Name; Full name\nCity,Town;Ort\n;Empty\nSingle\n").unwrap();
+        assert_eq!(
+            read_header_renames(&file).unwrap(),
+            vec![
+                ("Name".to_string(), "Full name".to_string()),
+                ("City,Town".to_string(), "Ort".to_string()),
+            ]
+        );
+
+        fs::write(&file, "\u{feff}Name,Full name\nCity,Ort\n").unwrap();
+        assert_eq!(
+            read_header_renames(&file).unwrap(),
+            vec![
+                ("Name".to_string(), "Full name".to_string()),
+                ("City".to_string(), "Ort".to_string()),
+            ]
+        );
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn header_fields_match_exactly() {
+        let mut headers = StringRecord::from(vec!["FirstName", "OrderYear", "Name", "Year"]);
+        assert_eq!(headers.get_field("Name").unwrap(), 2);
+        assert_eq!(headers.get_field("Year").unwrap(), 3);
+        assert!(headers.get_field("First").is_err());
+    }
+
+    #[test]
+    fn formulas_but_not_signed_numbers() {
+        assert!(is_formula("=SUM(A1:A3)"));
+        assert!(is_formula("+49 30 1234"));
+        assert!(is_formula("-cmd"));
+        assert!(is_formula("@SUM(A1)"));
+        assert!(is_formula("\t=1"));
+        assert!(!is_formula("-7,25"));
+        assert!(!is_formula("+4"));
+        assert!(!is_formula("-12,5 %"));
+        assert!(!is_formula("a=b"));
+        assert!(!is_formula(""));
     }
 }